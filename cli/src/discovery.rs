@@ -0,0 +1,353 @@
+use std::fmt;
+use std::path::{Path, PathBuf};
+
+use dataragon::encoding;
+use dataragon::objects::{CryptoSecretbox, SET_ID_LENGTH, Share};
+use dataragon::paper;
+use dataragon::serialization;
+use zeroize::Zeroize;
+
+use crate::json::to_hex;
+
+/// Poly1305 tag length of the encrypted box
+const TAG_LENGTH: usize = 16;
+/// The legacy share of 33 bytes is the shortest artifact, so the shorter words are skipped
+const MIN_BASE58_LEN: usize = 40;
+/// Length of the legacy share, it's a raw key share
+const LEGACY_SHARE_LEN: usize = 33;
+
+/// A share or an encrypted box found in a file
+#[derive(PartialEq, Debug)]
+pub enum Artifact {
+    Share(Share),
+    Secretbox(CryptoSecretbox),
+    /// The version 0 share has no set id, so it can't be grouped
+    LegacyShare,
+}
+
+impl fmt::Display for Artifact {
+    fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Artifact::Share(share) => write!(fmt, "share {} of the set {}, {} of {} needed", share.index(), to_hex(&share.set_id), share.threshold, share.count),
+            Artifact::Secretbox(secret_box) => write!(fmt, "encrypted box of the set {}", to_hex(&secret_box.set_id())),
+            Artifact::LegacyShare => write!(fmt, "legacy share without the set id and the checksum"),
+        }
+    }
+}
+
+/// The shares and the encrypted box of the same set stored in the same format version
+#[derive(PartialEq, Debug)]
+pub struct SetGroup {
+    pub set_id: [u8; SET_ID_LENGTH],
+    /// The format version of the stored shares
+    pub version: u64,
+    pub threshold: u8,
+    pub count: u8,
+    /// The shares with different indices
+    pub shares: Vec<Share>,
+    pub secret_box: Option<CryptoSecretbox>,
+}
+
+/// The key shares are zeroized when the set is dropped
+impl Drop for SetGroup {
+    fn drop(&mut self) {
+        self.shares.zeroize();
+    }
+}
+
+impl SetGroup {
+    pub fn is_recoverable(&self) -> bool {
+        self.secret_box.is_some() && self.shares.len() >= self.threshold as usize
+    }
+
+    pub fn indices(&self) -> Vec<u8> {
+        let mut indices: Vec<u8> = self.shares.iter().map(|share| share.index()).collect();
+        indices.sort_unstable();
+        indices
+    }
+}
+
+impl fmt::Display for SetGroup {
+    fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
+        let indices: Vec<String> = self.indices().iter().map(|i| i.to_string()).collect();
+        write!(fmt, "Set {} (format version {}): shares {{{}}} of {}, {} needed, ", to_hex(&self.set_id), self.version, indices.join(","), self.count, self.threshold)?;
+        write!(fmt, "{}, ", if self.secret_box.is_some() { "the encrypted box is found" } else { "the encrypted box is missing" })?;
+        write!(fmt, "{}", if self.is_recoverable() { "recoverable" } else { "not recoverable" })
+    }
+}
+
+/// Everything found in the directory
+#[derive(Debug)]
+pub struct Discovery {
+    pub sets: Vec<SetGroup>,
+    /// The duplicate shares and boxes, the damaged data and the files without any artifact
+    pub warnings: Vec<String>,
+}
+
+/// The shares and the encrypted boxes read from the stored data are told apart by their full structure
+pub fn classify_payload(payload: &[u8]) -> Option<Artifact> {
+    let secret_box = postcard::take_from_bytes::<CryptoSecretbox>(payload).ok()
+        .filter(|(secret_box, rest)| rest.is_empty() && secret_box.tag.len() == TAG_LENGTH)
+        .map(|(secret_box, _)| Artifact::Secretbox(secret_box));
+    secret_box.or_else(|| postcard::take_from_bytes::<Share>(payload).ok()
+        .filter(|(share, rest)| rest.is_empty() && share.threshold >= 1 && share.threshold <= share.count)
+        .map(|(share, _)| Artifact::Share(share)))
+}
+
+/// The legacy share is a raw key share, it can't be checked
+pub fn is_legacy_share(data: &[u8], erasures: &[usize]) -> bool {
+    data.len() == LEGACY_SHARE_LEN && erasures.is_empty()
+}
+
+/// The artifact with the format version of its stored data, the legacy share is of the version 0
+fn classify(data: &[u8], erasures: &[usize]) -> Option<(u64, Artifact)> {
+    match serialization::try_to_read_stored_data_with_report(data, erasures) {
+        Ok((payload, report)) => classify_payload(payload.as_slice()).map(|artifact| (report.format_version, artifact)),
+        Err(_) if is_legacy_share(data, erasures) => Some((0, Artifact::LegacyShare)),
+        Err(_) => None,
+    }
+}
+
+/// Finds the stored data with its known damaged positions in any text: the paper lines separated by empty lines
+/// and the base58 words, so the JSON documents, the share files, the `split` output and the emails are read the same way
+pub fn stored_data_in_text(text: &str) -> Vec<(Vec<u8>, Vec<usize>)> {
+    let paper_data = text.split("\n\n")
+        .filter(|block| block.lines().any(paper::is_valid_line))
+        .filter_map(|block| paper::decode_lines(block).ok())
+        .map(|transcription| (transcription.data, transcription.erasures));
+    let base58_data = text.split(|c: char| !c.is_ascii_alphanumeric() && c != '?')
+        .filter(|word| word.len() >= MIN_BASE58_LEN)
+        .filter_map(|word| encoding::decode_base58_with_erasures(word).ok());
+    paper_data.chain(base58_data).collect()
+}
+
+/// The binary file is read as the stored data
+pub fn stored_data_in_file(bytes: &[u8]) -> Vec<(Vec<u8>, Vec<usize>)> {
+    match std::str::from_utf8(bytes) {
+        Ok(text) => stored_data_in_text(text.replace("\r\n", "\n").as_str()),
+        Err(_) => vec![(bytes.to_vec(), Vec::new())],
+    }
+}
+
+/// The artifacts of the file with their format versions.
+/// The stored data which can't be read alone is added to `damaged`, it can be read together with its other copies.
+pub fn artifacts_in_file(bytes: &[u8], damaged: &mut Vec<(Vec<u8>, Vec<usize>)>) -> Vec<(u64, Artifact)> {
+    let mut artifacts = Vec::new();
+    for (data, erasures) in stored_data_in_file(bytes) {
+        match classify(data.as_slice(), erasures.as_slice()) {
+            Some(artifact) => artifacts.push(artifact),
+            None => damaged.push((data, erasures)),
+        }
+    }
+    artifacts
+}
+
+/// All the files of the directory and its subdirectories in the order of their names
+pub fn files_in(dir: &Path, warnings: &mut Vec<String>) -> Vec<PathBuf> {
+    let mut entries: Vec<PathBuf> = match std::fs::read_dir(dir) {
+        Ok(entries) => entries.filter_map(|entry| entry.ok()).map(|entry| entry.path()).collect(),
+        Err(e) => {
+            warnings.push(format!("Can't read {}: {}", dir.display(), e));
+            return Vec::new();
+        }
+    };
+    entries.sort();
+    entries.into_iter()
+        .flat_map(|path| if path.is_dir() { files_in(path.as_path(), warnings) } else { vec![path] })
+        .collect()
+}
+
+/// Groups the artifacts by the set id and the format version of the shares.
+/// The copies of the same share and the other shares with the same index are skipped with a warning.
+/// The set id is the hash of the encrypted box, so the other boxes of the same set are its copies,
+/// they're skipped with a warning too.
+pub fn group(artifacts: Vec<(PathBuf, u64, Artifact)>, warnings: &mut Vec<String>) -> Vec<SetGroup> {
+    let mut sets: Vec<SetGroup> = Vec::new();
+    let mut secret_boxes: Vec<CryptoSecretbox> = Vec::new();
+    for (path, version, artifact) in artifacts {
+        match artifact {
+            Artifact::Share(share) => {
+                let set = match sets.iter().position(|set| set.set_id == share.set_id && set.version == version) {
+                    Some(i) => &mut sets[i],
+                    None => {
+                        sets.push(SetGroup { set_id: share.set_id, version, threshold: share.threshold, count: share.count, shares: Vec::new(), secret_box: None });
+                        sets.last_mut().unwrap()
+                    }
+                };
+                match set.shares.iter().find(|s| s.index() == share.index()) {
+                    Some(s) if *s == share => warnings.push(format!("The share {} of the set {} in {} is a duplicate", share.index(), to_hex(&share.set_id), path.display())),
+                    Some(_) => warnings.push(format!("The share {} of the set {} in {} differs from the one found before, it's skipped", share.index(), to_hex(&share.set_id), path.display())),
+                    None => set.shares.push(share),
+                }
+            }
+            Artifact::Secretbox(secret_box) => if secret_boxes.contains(&secret_box) {
+                warnings.push(format!("The encrypted box of the set {} in {} is a duplicate", to_hex(&secret_box.set_id()), path.display()));
+            } else {
+                secret_boxes.push(secret_box);
+            },
+            Artifact::LegacyShare => warnings.push(format!("The legacy share in {} has no set id, migrate it to find its set", path.display())),
+        }
+    }
+    for secret_box in secret_boxes {
+        let set_id = secret_box.set_id();
+        let mut matched = false;
+        for set in sets.iter_mut().filter(|set| set.set_id == set_id) {
+            set.secret_box = Some(secret_box.clone());
+            matched = true;
+        }
+        if !matched {
+            warnings.push(format!("The encrypted box of the set {} has no shares", to_hex(&set_id)));
+        }
+    }
+    sets
+}
+
+/// The damaged copies of the same stored data have the same length, they're read together.
+/// The data which isn't read even with its copies is skipped, it can be any other long word.
+/// The artifacts are returned with all the files of their copies.
+pub fn read_damaged_copies(damaged: Vec<(PathBuf, Vec<u8>, Vec<usize>)>, warnings: &mut Vec<String>) -> Vec<(Vec<PathBuf>, u64, Artifact)> {
+    let mut lengths: Vec<usize> = damaged.iter().map(|(_, data, _)| data.len()).collect();
+    lengths.sort_unstable();
+    lengths.dedup();
+    let mut artifacts = Vec::new();
+    for len in lengths {
+        let same_len: Vec<&(PathBuf, Vec<u8>, Vec<usize>)> = damaged.iter().filter(|(_, data, _)| data.len() == len).collect();
+        if same_len.len() < 2 {
+            continue;
+        }
+        let copies: Vec<(&[u8], &[usize])> = same_len.iter().map(|(_, data, erasures)| (data.as_slice(), erasures.as_slice())).collect();
+        let artifact = serialization::try_to_read_stored_data_from_copies(copies.as_slice()).ok()
+            .and_then(|(payload, report)| classify_payload(payload.as_slice()).map(|artifact| (report.format_version, artifact)));
+        if let Some((version, artifact)) = artifact {
+            let mut paths: Vec<PathBuf> = same_len.iter().map(|(path, _, _)| path.clone()).collect();
+            paths.dedup();
+            let names: Vec<String> = paths.iter().map(|path| path.display().to_string()).collect();
+            warnings.push(format!("The damaged copies in {} are read together: {}", names.join(", "), artifact));
+            artifacts.push((paths, version, artifact));
+        }
+    }
+    artifacts
+}
+
+/// Reads every file of the directory and groups the shares and the encrypted boxes found in them
+pub fn discover(dir: &Path) -> Discovery {
+    let mut warnings = Vec::new();
+    let mut artifacts = Vec::new();
+    let mut damaged = Vec::new();
+    let files = files_in(dir, &mut warnings);
+    // The files with the artifacts and the ones which can't be read are already reported
+    let mut reported_files = Vec::new();
+    for path in files.iter() {
+        match std::fs::read(path.as_path()) {
+            Ok(bytes) => {
+                let mut damaged_in_file = Vec::new();
+                let found = artifacts_in_file(bytes.as_slice(), &mut damaged_in_file);
+                if !found.is_empty() {
+                    reported_files.push(path.clone());
+                }
+                artifacts.extend(found.into_iter().map(|(version, artifact)| (path.clone(), version, artifact)));
+                damaged.extend(damaged_in_file.into_iter().map(|(data, erasures)| (path.clone(), data, erasures)));
+            }
+            Err(e) => {
+                warnings.push(format!("Can't read {}: {}", path.display(), e));
+                reported_files.push(path.clone());
+            }
+        }
+    }
+    for (paths, version, artifact) in read_damaged_copies(damaged, &mut warnings) {
+        artifacts.push((paths[0].clone(), version, artifact));
+        reported_files.extend(paths);
+    }
+    for path in files.iter().filter(|path| !reported_files.contains(path)) {
+        warnings.push(format!("No shares or encrypted boxes in {}", path.display()));
+    }
+    let sets = group(artifacts, &mut warnings);
+    Discovery { sets, warnings }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use dataragon::planner::EccPlan;
+
+    fn split_output(secret: &str) -> (Vec<String>, String) {
+        let (shares, secret_box) = dataragon::split(secret.as_bytes(), 1.0, 3, 2).unwrap();
+        let stored_box = serialization::add_ecc_and_crc(serialization::to_bytes(&secret_box).unwrap(), 1.0).unwrap();
+        (shares.iter().map(|share| bs58::encode(share).into_string()).collect(), bs58::encode(stored_box).into_string())
+    }
+
+    fn artifacts_of_texts(texts: &[String]) -> Vec<(PathBuf, u64, Artifact)> {
+        texts.iter().enumerate()
+            .flat_map(|(i, text)| artifacts_in_file(text.as_bytes(), &mut Vec::new()).into_iter()
+                .map(move |(version, artifact)| (PathBuf::from(i.to_string()), version, artifact)))
+            .collect()
+    }
+
+    #[test]
+    fn artifacts_are_found_in_any_text() {
+        let (shares, secret_box) = split_output("supersecret");
+        let email = format!("Hi,\r\nhere is my share: \"{}\".\r\nThe box:\r\n{}\r\n", shares[0], secret_box);
+
+        let artifacts = artifacts_in_file(email.as_bytes(), &mut Vec::new());
+
+        assert_eq!(artifacts.len(), 2);
+        assert!(matches!(artifacts[0], (1, Artifact::Share(_))));
+        assert!(matches!(artifacts[1], (1, Artifact::Secretbox(_))));
+        assert!(artifacts_in_file("Shares: [\"abc\"]".as_bytes(), &mut Vec::new()).is_empty());
+    }
+
+    #[test]
+    fn shares_are_grouped_by_the_set() {
+        let (shares, secret_box) = split_output("supersecret");
+        let (other_shares, _) = split_output("othersecret");
+        let texts = [shares[0].clone(), shares[0].clone(), format!("{}\n{}", shares[2], secret_box), other_shares[1].clone(), secret_box.clone()];
+        let mut warnings = Vec::new();
+
+        let sets = group(artifacts_of_texts(&texts), &mut warnings);
+
+        assert_eq!(sets.len(), 2);
+        assert_eq!(sets[0].indices(), vec![1, 3]);
+        assert!(sets[0].is_recoverable());
+        assert!(!sets[1].is_recoverable());
+        assert_eq!(warnings.len(), 2);
+        assert!(warnings[1].contains("encrypted box") && warnings[1].contains("duplicate"));
+    }
+
+    #[test]
+    fn shares_of_different_versions_are_grouped_apart() {
+        let (shares, secret_box) = split_output("supersecret");
+        let mut artifacts = artifacts_of_texts(&[shares[0].clone(), shares[1].clone(), secret_box]);
+        artifacts[1].1 = 2;
+
+        let sets = group(artifacts, &mut Vec::new());
+
+        assert_eq!(sets.iter().map(|set| (set.version, set.indices())).collect::<Vec<_>>(), vec![(1, vec![1]), (2, vec![2])]);
+        assert!(sets.iter().all(|set| set.secret_box.is_some() && !set.is_recoverable()));
+    }
+
+    #[test]
+    fn damaged_copies_of_the_box_are_read_together() {
+        let (shares, secret_box) = dataragon::split("supersecret".as_bytes(), 1.0, 3, 2).unwrap();
+        let payload = serialization::to_bytes(&secret_box).unwrap();
+        let stored_box = serialization::add_ecc_and_crc_with_plan(payload.clone(), &EccPlan::new(payload.len(), 0, 1)).unwrap();
+        let offset = stored_box.windows(payload.len()).position(|window| window == payload.as_slice()).unwrap();
+        let dir = std::env::temp_dir().join(format!("dataragon-damaged-copies-{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(&dir).unwrap();
+        for i in 0..3 {
+            let mut damaged = stored_box.clone();
+            damaged[offset + i] ^= 0xff;
+            assert!(serialization::try_to_read_stored_data(damaged.as_slice()).is_err());
+            std::fs::write(dir.join(format!("box{}.txt", i)), bs58::encode(damaged).into_string()).unwrap();
+        }
+        std::fs::write(dir.join("shares.txt"), shares.iter().map(|share| bs58::encode(share).into_string()).collect::<Vec<_>>().join("\n")).unwrap();
+
+        let found = discover(dir.as_path());
+
+        assert_eq!(found.sets.len(), 1);
+        assert_eq!(found.sets[0].secret_box.as_ref(), Some(&secret_box));
+        assert_eq!(found.warnings.len(), 1, "{:?}", found.warnings);
+        assert!(found.warnings[0].contains("read together"));
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+}
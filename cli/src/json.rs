@@ -0,0 +1,170 @@
+use serde::{Deserialize, Serialize};
+
+use dataragon::planner::EccPlan;
+
+/// Version of the JSON documents, it's changed only if the old readers can't read them
+pub const DOCUMENT_VERSION: u32 = 1;
+
+/// A share with its set information, it's written in its own file for a share holder
+#[derive(Serialize, Deserialize, PartialEq, Debug, Clone)]
+pub struct ShareDocument {
+    /// Version of the share file, the shares in the split document have its version
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub version: Option<u32>,
+    pub index: u8,
+    /// Set id in hex, the same for all the shares of the encrypted box
+    pub set_id: String,
+    pub threshold: u8,
+    pub count: u8,
+    /// The share in base58
+    pub share: String,
+    /// Name of the share holder, it's only in the share file
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub holder: Option<String>,
+    /// When and by which version the share file was written
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub created: Option<String>,
+    /// How to recover the secret, for the holder who doesn't know dataragon
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub instructions: Option<String>,
+    /// The secret fingerprint in hex to compare in `combine --drill`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub fingerprint: Option<String>,
+}
+
+/// The ECC plan of the encrypted box
+#[derive(Serialize, Deserialize, PartialEq, Debug, Clone)]
+pub struct PlanDocument {
+    pub reed_solomon_ecc_len: usize,
+    pub copies: usize,
+    pub header_copies: usize,
+    pub header_reed_solomon_ecc_len: usize,
+}
+
+/// Everything written by `split`
+#[derive(Serialize, Deserialize, PartialEq, Debug, Clone)]
+pub struct SplitDocument {
+    pub version: u32,
+    pub set_id: String,
+    pub threshold: u8,
+    pub count: u8,
+    pub shares: Vec<ShareDocument>,
+    /// The encrypted box in base58
+    pub secretbox: String,
+    pub format_version: u64,
+    pub plan: PlanDocument,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub fingerprint: Option<String>,
+}
+
+/// The outcome of `combine --drill` without the secret.
+/// The tag is made with the recovered key over this JSON document with the empty tag.
+#[derive(Serialize, Deserialize, PartialEq, Debug, Clone)]
+pub struct DrillRecord {
+    pub version: u32,
+    pub created: String,
+    pub set_id: String,
+    /// Indices of the combined shares
+    pub shares: Vec<u8>,
+    pub fingerprint: String,
+    /// Whether the fingerprint equals the expected one, `None` when no fingerprint was given to compare with
+    pub fingerprint_matches: Option<bool>,
+    pub tag: String,
+}
+
+/// A document passed to `combine`: the whole split output or a single share
+#[derive(Deserialize, PartialEq, Debug, Clone)]
+#[serde(untagged)]
+pub enum InputDocument {
+    Split(SplitDocument),
+    Share(ShareDocument),
+}
+
+pub fn to_hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+impl From<&EccPlan> for PlanDocument {
+    fn from(plan: &EccPlan) -> Self {
+        PlanDocument {
+            reed_solomon_ecc_len: plan.reed_solomon_ecc_len,
+            copies: plan.copies,
+            header_copies: plan.header_copies,
+            header_reed_solomon_ecc_len: plan.header_reed_solomon_ecc_len,
+        }
+    }
+}
+
+/// The document written by a newer dataragon can't be read
+fn check_version(version: u32) -> Result<(), String> {
+    if version == DOCUMENT_VERSION {
+        Ok(())
+    } else {
+        Err(format!("The document version {} isn't supported, please update dataragon", version))
+    }
+}
+
+impl ShareDocument {
+    /// The shares in the split document have no version of their own
+    pub fn check_version(&self) -> Result<(), String> {
+        self.version.map(check_version).unwrap_or(Ok(()))
+    }
+}
+
+impl DrillRecord {
+    pub fn check_version(&self) -> Result<(), String> {
+        check_version(self.version)
+    }
+
+    /// The record with the empty tag the tag is made over
+    pub fn authenticated_bytes(&self) -> Vec<u8> {
+        serde_json::to_vec(&DrillRecord { tag: String::new(), ..self.clone() }).unwrap()
+    }
+}
+
+impl InputDocument {
+    pub fn check_version(&self) -> Result<(), String> {
+        match self {
+            InputDocument::Split(split) => check_version(split.version),
+            InputDocument::Share(share) => share.check_version(),
+        }
+    }
+
+    pub fn shares(&self) -> Vec<&ShareDocument> {
+        match self {
+            InputDocument::Split(split) => split.shares.iter().collect(),
+            InputDocument::Share(share) => vec![share],
+        }
+    }
+
+    pub fn fingerprint(&self) -> Option<&str> {
+        match self {
+            InputDocument::Split(split) => split.fingerprint.as_deref(),
+            InputDocument::Share(share) => share.fingerprint.as_deref(),
+        }
+    }
+
+    pub fn secretbox(&self) -> Option<&str> {
+        match self {
+            InputDocument::Split(split) => Some(split.secretbox.as_str()),
+            InputDocument::Share(_) => None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn unknown_document_versions_are_rejected() {
+        let share = r#"{"index": 1, "set_id": "00", "threshold": 2, "count": 3, "share": "2b"}"#;
+        let share_file = r#"{"version": 1, "index": 1, "set_id": "00", "threshold": 2, "count": 3, "share": "2b"}"#;
+        let future_share_file = r#"{"version": 2, "index": 1, "set_id": "00", "threshold": 2, "count": 3, "share": "2b"}"#;
+
+        for (text, supported) in [(share, true), (share_file, true), (future_share_file, false)].iter() {
+            let document: InputDocument = serde_json::from_str(text).unwrap();
+            assert_eq!(document.check_version().is_ok(), *supported, "{}", text);
+        }
+    }
+}
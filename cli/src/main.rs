@@ -1,11 +1,54 @@
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
+use std::io::{IsTerminal, Read, Write};
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use std::time::SystemTime;
 
+use base64::Engine;
 use itertools::Itertools;
-use map_in_place::MapVecInPlace;
 use structopt::StructOpt;
+use zeroize::Zeroizing;
 
 use dataragon::objects::*;
+use dataragon::planner;
+use dataragon::planner::{EccPlan, MediaProfile};
+use dataragon::encoding;
+use dataragon::encoding::TextEncoding;
+use dataragon::paper;
 use dataragon::serialization;
+use dataragon::simulator;
+use dataragon::simulator::Damage;
+
+mod discovery;
+mod json;
+
+/// The encrypted box file name in the `split --out-dir` directory
+const SECRETBOX_FILE_NAME: &str = "secretbox.txt";
+
+/// How the combined secret is written
+#[derive(Debug, Copy, Clone, PartialEq)]
+enum OutputFormat {
+    /// The bytes as they are
+    Raw,
+    Hex,
+    Base64,
+    /// The text, it's refused if it's not UTF-8
+    Utf8,
+}
+
+impl FromStr for OutputFormat {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "raw" => Ok(OutputFormat::Raw),
+            "hex" => Ok(OutputFormat::Hex),
+            "base64" => Ok(OutputFormat::Base64),
+            "utf8" | "utf-8" => Ok(OutputFormat::Utf8),
+            _ => Err(format!("Unknown output format '{}', use raw, hex, base64 or utf8", s)),
+        }
+    }
+}
 
 #[derive(Debug, StructOpt)]
 #[structopt(name = "Dataragon", about = "Dataragon splits the secret to the shares and recovers them")]
@@ -20,46 +63,1175 @@ enum DataragonCommands {
         #[structopt(short = "t", long = "threshold")]
         // Threshold
         threshold: u8,
+        #[structopt(long = "profile")]
+        /// Storage media of the encrypted box: paper, qr or disk.
+        ///
+        /// The error-correcting code is planned to survive the typical damage of the media.
+        profile: Option<MediaProfile>,
+        #[structopt(long = "paper")]
+        /// Writes the shares and the encrypted box as the numbered lines with checksums to be copied by hand
+        paper: bool,
+        #[structopt(long = "json", conflicts_with = "paper")]
+        /// Writes the shares, the encrypted box and their information as a JSON document
+        json: bool,
+        #[structopt(long = "out-dir", conflicts_with_all = &["paper", "json"])]
+        /// Writes a file for every share holder and the encrypted box file to the directory
+        out_dir: Option<String>,
+        #[structopt(long = "holders", use_delimiter = true, requires = "out-dir")]
+        /// Comma-separated names of the share holders, one for every share, the share files are named by them
+        holders: Vec<String>,
+        #[structopt(long = "input", conflicts_with_all = &["stdin", "input-fd"])]
+        /// Reads the secret from the file as it is, e.g. an SSH key or binary key material
+        input: Option<String>,
+        #[structopt(long = "stdin", conflicts_with = "input-fd")]
+        /// Reads the secret from stdin until its end
+        stdin: bool,
+        #[structopt(long = "input-fd")]
+        /// Reads the secret from the open file descriptor until its end
+        input_fd: Option<i32>,
     },
     #[structopt(name = "combine")]
     Combine {
+        #[structopt(long = "shares")]
+        /// Share, mark the unreadable characters with '?', they will be restored with the error-correcting code
+        shares: Vec<String>,
+        #[structopt(long = "shares-paper")]
+        /// File with the lines of a share written by `split --paper`
+        shares_paper: Vec<String>,
+        #[structopt(long = "json")]
+        /// File with the JSON document written by `split --json` or a share document, `-` for stdin
+        json: Vec<String>,
+        #[structopt(long = "share-file")]
+        /// Share file written by `split --out-dir`
+        share_file: Vec<String>,
+        #[structopt(long = "from-dir", conflicts_with_all = &["shares", "shares-paper", "json", "share-file", "secretbox", "secretbox-paper", "secretbox-file", "repair", "drill"])]
+        /// Finds the shares and the encrypted boxes in all the files of the directory and recovers every set which has enough shares.
+        ///
+        /// Several secrets are written to the files named by `--output` and the set id.
+        from_dir: Option<String>,
+        #[structopt(long = "secretbox", required_unless_one = &["secretbox-paper", "json", "secretbox-file", "from-dir"])]
+        /// Encrypted box, pass it several times to read the damaged copies of the same box together.
+        ///
+        /// Mark the unreadable characters with '?', they will be restored with the error-correcting code.
+        secretbox: Vec<String>,
+        #[structopt(long = "secretbox-paper")]
+        /// File with the lines of the encrypted box written by `split --paper`, in any order.
+        ///
+        /// The missing and mistyped lines are restored with the error-correcting code.
+        secretbox_paper: Vec<String>,
+        #[structopt(long = "secretbox-file")]
+        /// Encrypted box file written by `split --out-dir`
+        secretbox_file: Vec<String>,
+        #[structopt(long = "repair")]
+        /// Repairs the mistyped characters of the shares and the encrypted box which can't be read.
+        ///
+        /// The confused characters, swapped, missed and extra ones are tried, it can take a while.
+        /// The legacy shares have no checksum, so they aren't repaired.
+        repair: bool,
+        #[structopt(long = "output", conflicts_with = "output-fd")]
+        /// Writes the secret to the file instead of stdout
+        output: Option<String>,
+        #[structopt(long = "output-fd")]
+        /// Writes the secret to the open file descriptor instead of stdout
+        output_fd: Option<i32>,
+        #[structopt(long = "format", default_value = "raw")]
+        /// Format of the secret: raw, hex, base64 or utf8
+        format: OutputFormat,
+        #[structopt(long = "force")]
+        /// Writes the binary secret to the terminal
+        force: bool,
+        #[structopt(long = "drill", conflicts_with_all = &["output", "output-fd", "force"])]
+        /// Recovers the secret without writing it and shows the combined shares, the secret and the key are zeroized.
+        ///
+        /// The secret fingerprint is compared with `--fingerprint` or the one in the JSON documents and the share files.
+        drill: bool,
+        #[structopt(long = "fingerprint", requires = "drill")]
+        /// The secret fingerprint written by `split` to compare in the drill
+        fingerprint: Option<String>,
+        #[structopt(long = "record", requires = "drill")]
+        /// Writes the drill record authenticated by the recovered key to the file
+        record: Option<String>,
+        #[structopt(long = "verify-record", requires = "drill")]
+        /// Checks the tag of the drill record written before with the recovered key.
+        ///
+        /// The tag is made by a key only the shares of the set recover, so the record can't be changed without them.
+        verify_record: Option<String>,
+    },
+    #[structopt(name = "migrate")]
+    /// Stores the shares and the encrypted box of any format version in the latest one
+    Migrate {
         #[structopt(long = "shares")]
         shares: Vec<String>,
         #[structopt(long = "secretbox")]
         secretbox: String,
+        #[structopt(short = "c", long = "count")]
+        /// Number of the shares in the set, it's written to the legacy shares
+        count: u8,
+        #[structopt(short = "t", long = "threshold")]
+        /// Minimal number of the shares to recover the secret, it's written to the legacy shares
+        threshold: u8,
+        #[structopt(long = "profile")]
+        /// Storage media of the encrypted box: paper, qr or disk
+        profile: Option<MediaProfile>,
+        #[structopt(long = "repair")]
+        /// Repairs the mistyped characters of the shares and the encrypted box which can't be read, like `combine --repair`
+        repair: bool,
+    },
+    #[structopt(name = "plan")]
+    /// Plans the error-correcting code and shows the damage it's guaranteed to survive
+    Plan {
+        #[structopt(short = "s", long = "size")]
+        /// Size of the protected data in bytes
+        size: usize,
+        #[structopt(long = "random")]
+        /// Number of the bytes which can be damaged in any positions of the stored data
+        random: Option<usize>,
+        #[structopt(long = "burst")]
+        /// Length of the sequential damaged bytes
+        burst: Option<usize>,
+        #[structopt(long = "profile")]
+        /// Storage media: paper, qr or disk
+        profile: Option<MediaProfile>,
+        #[structopt(long = "level")]
+        /// Explains the plan for the allowed data damage level instead
+        level: Option<f32>,
+    },
+    #[structopt(name = "armor")]
+    /// Wraps any file in the error-correcting code and writes it as text, without the secret sharing
+    Armor {
+        /// The file to armor
+        file: String,
+        #[structopt(long = "profile")]
+        /// Storage media of the armored text: paper, qr or disk
+        profile: Option<MediaProfile>,
+        #[structopt(long = "level")]
+        /// Allowed data damage level if there's no profile
+        level: Option<f32>,
+        #[structopt(long = "encoding", default_value = "base58")]
+        /// Text encoding: base58 or paper
+        encoding: TextEncoding,
+    },
+    #[structopt(name = "unarmor")]
+    /// Restores the file from the armored text
+    Unarmor {
+        /// File with the armored text, it's read from stdin if there's no file
+        file: Option<String>,
+        #[structopt(long = "encoding", default_value = "base58")]
+        /// Text encoding: base58 or paper
+        encoding: TextEncoding,
+        #[structopt(long = "output")]
+        /// The restored file, it's written to stdout if there's no file
+        output: Option<String>,
     },
+    #[structopt(name = "verify")]
+    /// Checks that the shares and the encrypted boxes are still readable, without recovering the secret.
+    ///
+    /// Exits with 1 if any of them is read only thanks to the error-correcting code which can correct fewer than
+    /// `--min-margin` more errors, or its header copies barely agree, so it should be made again,
+    /// and with 2 if it can't be read or a share doesn't belong to the checked encrypted boxes.
+    Verify {
+        /// Files or directories with the shares and the encrypted boxes in any format `combine --from-dir` reads
+        files: Vec<String>,
+        #[structopt(long = "shares")]
+        shares: Vec<String>,
+        #[structopt(long = "secretbox")]
+        secretbox: Vec<String>,
+        #[structopt(long = "min-margin", default_value = "4")]
+        /// The number of the errors the error-correcting code must still be able to correct in the repaired artifact
+        min_margin: usize,
+    },
+    #[structopt(name = "inspect")]
+    /// Decodes a share, an encrypted box or any stored data and shows what's inside, the other shares aren't needed
+    Inspect {
+        /// The base58 string, or the file with the text, the paper lines, the JSON document or the binary stored data
+        artifact: String,
+    },
+    #[structopt(name = "simulate")]
+    /// Damages the stored random data in many trials and shows how often it's recovered
+    Simulate {
+        #[structopt(short = "s", long = "size", default_value = "40")]
+        /// Size of the protected data in bytes
+        size: usize,
+        #[structopt(long = "level", required = true)]
+        /// Allowed data damage level to compare, pass it several times
+        level: Vec<f32>,
+        #[structopt(long = "damage", required = true)]
+        /// Damage model: random:<fraction>, burst:<bytes>, bits:<fraction>, cut:<bytes> or typos:<count>
+        damage: Vec<Damage>,
+        #[structopt(long = "trials", default_value = "1000")]
+        trials: usize,
+        #[structopt(long = "seed", default_value = "0")]
+        /// Seed of the random damage, the same seed repeats the trials
+        seed: u64,
+    },
+}
+
+/// The plan for the media profile, otherwise for the allowed data damage level
+fn ecc_plan(data_len: usize, profile: Option<MediaProfile>, allowed_data_damage_level: f32) -> dataragon::error::Result<EccPlan> {
+    match profile {
+        Some(profile) => planner::plan(data_len, &profile.damage_target()),
+        None => Ok(EccPlan::from_damage_level(data_len, allowed_data_damage_level)),
+    }
+}
+
+/// Opens the file descriptor passed by the calling process.
+/// The standard streams are refused, they'd be closed with the file.
+#[cfg(unix)]
+fn file_from_fd(fd: i32) -> std::io::Result<std::fs::File> {
+    use std::os::unix::io::FromRawFd;
+    if fd <= 2 {
+        return Err(std::io::Error::new(std::io::ErrorKind::InvalidInput,
+                                       "the descriptors 0, 1 and 2 are the standard streams, use --stdin or omit --output-fd"));
+    }
+    // the descriptor is owned by the process from now, it's closed with the file
+    Ok(unsafe { std::fs::File::from_raw_fd(fd) })
 }
 
-fn split(count: u8, threshold: u8) {
-    let read_password = rpassword::prompt_password("Enter your secret (the input is hidden): ");
-    let password = read_password
-        .unwrap_or_else(|_| rpassword::prompt_password("Enter your secret (the input is hidden): ").unwrap());
+#[cfg(not(unix))]
+fn file_from_fd(_fd: i32) -> std::io::Result<std::fs::File> {
+    Err(std::io::Error::new(std::io::ErrorKind::Unsupported, "the file descriptors are supported only on Unix"))
+}
+
+/// Reads the secret bytes from the file, stdin or the file descriptor, otherwise it's prompted
+fn read_secret(input: Option<String>, stdin: bool, input_fd: Option<i32>) -> Result<Vec<u8>, String> {
+    let mut secret = Vec::new();
+    match (input, input_fd) {
+        (Some(file), _) => secret = std::fs::read(file.as_str()).map_err(|e| format!("Can't read {}: {}", file, e))?,
+        (None, Some(fd)) => {
+            file_from_fd(fd).and_then(|mut file| file.read_to_end(&mut secret))
+                .map_err(|e| format!("Can't read the input file descriptor: {}", e))?;
+        }
+        (None, None) if stdin => {
+            std::io::stdin().read_to_end(&mut secret).map_err(|e| format!("Can't read stdin: {}", e))?;
+        }
+        (None, None) => {
+            secret = rpassword::prompt_password("Enter your secret (the input is hidden): ")
+                .map_err(|e| format!("Can't read the secret: {}", e))?
+                .into_bytes();
+        }
+    }
+    Ok(secret)
+}
+
+/// The JSON document with the shares, the encrypted box and their information
+fn split_document(shares: &[Vec<u8>], secret_box: &CryptoSecretbox, encoded_secret_box: &[u8], plan: &EccPlan, threshold: u8, count: u8,
+                  fingerprint: &str) -> dataragon::error::Result<json::SplitDocument> {
+    let set_id = json::to_hex(&secret_box.set_id());
+    let share_documents = shares.iter().map(|share| dataragon::decode_share(share.as_slice()).map(|decoded| json::ShareDocument {
+        version: None,
+        index: decoded.index(),
+        set_id: set_id.clone(),
+        threshold,
+        count,
+        share: bs58::encode(share).into_string(),
+        holder: None,
+        created: None,
+        instructions: None,
+        fingerprint: Some(fingerprint.to_string()),
+    })).collect::<dataragon::error::Result<Vec<json::ShareDocument>>>()?;
+    Ok(json::SplitDocument {
+        version: json::DOCUMENT_VERSION,
+        set_id: set_id.clone(),
+        threshold,
+        count,
+        shares: share_documents,
+        secretbox: bs58::encode(encoded_secret_box).into_string(),
+        format_version: serialization::FORMAT_VERSION,
+        plan: json::PlanDocument::from(plan),
+        fingerprint: Some(fingerprint.to_string()),
+    })
+}
+
+/// The share file name of the holder, the characters unsafe in a file name are replaced
+fn share_file_name(holder: &str) -> String {
+    let name: String = holder.trim().chars()
+        .map(|c| if c.is_alphanumeric() || c == '-' || c == '_' { c } else { '_' })
+        .collect();
+    format!("{}.share.json", name)
+}
+
+fn recovery_instructions(share: &json::ShareDocument) -> String {
+    format!("This is the share {} of {} of a secret split by dataragon, any {} shares of the set {} recover it. \
+             Keep it private and don't copy it to the other holders. \
+             To recover the secret, collect {} share files and the encrypted box file {} and run: \
+             dataragon-cli combine --share-file <share file> --share-file <share file> --secretbox-file {}",
+            share.index, share.count, share.threshold, share.set_id, share.threshold, SECRETBOX_FILE_NAME, SECRETBOX_FILE_NAME)
+}
+
+/// The current time and the version which made the file
+fn created() -> String {
+    format!("{} by dataragon-cli {}", humantime::format_rfc3339_seconds(SystemTime::now()), env!("CARGO_PKG_VERSION"))
+}
 
-    let text = password.as_bytes();
+/// Fails if the file exists, the shares of another set must not be lost
+fn write_new_file(path: &Path, bytes: &[u8]) -> std::io::Result<()> {
+    std::fs::OpenOptions::new().write(true).create_new(true).open(path)
+        .and_then(|mut file| file.write_all(bytes))
+}
+
+/// Writes a file for every share holder and the encrypted box file.
+/// The shares without the holders are named by their indices.
+/// If a file can't be written, the written ones are removed, so a half of the set isn't left.
+fn write_share_files(out_dir: &str, holders: Vec<String>, document: &json::SplitDocument) -> Result<Vec<PathBuf>, String> {
+    let holders = if holders.is_empty() {
+        document.shares.iter().map(|share| format!("share-{}", share.index)).collect()
+    } else {
+        holders
+    };
+    if holders.len() != document.shares.len() {
+        return Err(format!("There are {} holders for {} shares", holders.len(), document.shares.len()));
+    }
+    if holders.iter().any(|holder| holder.trim().is_empty()) {
+        return Err("The holders names can't be blank".to_string());
+    }
+    let file_names: HashSet<String> = holders.iter().map(|holder| share_file_name(holder)).collect();
+    if file_names.len() != holders.len() {
+        return Err("The holders names must be different".to_string());
+    }
+    std::fs::create_dir_all(out_dir).map_err(|e| format!("Can't create {}: {}", out_dir, e))?;
+    let created = created();
+    let mut written = Vec::new();
+    for (share, holder) in document.shares.iter().zip(holders.iter()) {
+        let share_file = json::ShareDocument {
+            version: Some(json::DOCUMENT_VERSION),
+            holder: Some(holder.trim().to_string()),
+            created: Some(created.clone()),
+            instructions: Some(recovery_instructions(share)),
+            ..share.clone()
+        };
+        written.push((Path::new(out_dir).join(share_file_name(holder)), serde_json::to_string_pretty(&share_file).unwrap()));
+    }
+    written.push((Path::new(out_dir).join(SECRETBOX_FILE_NAME), format!("{}\n", document.secretbox)));
+    let mut paths: Vec<PathBuf> = Vec::new();
+    for (path, text) in written.into_iter() {
+        if let Err(e) = write_new_file(&path, text.as_bytes()) {
+            paths.iter().for_each(|written| {
+                let _ = std::fs::remove_file(written);
+            });
+            return Err(format!("Can't write {}: {}", path.display(), e));
+        }
+        paths.push(path);
+    }
+    Ok(paths)
+}
+
+fn split(count: u8, threshold: u8, profile: Option<MediaProfile>, paper: bool, json: bool, out_dir: Option<String>, holders: Vec<String>, secret: Vec<u8>) {
+    let text = secret.as_slice();
     let allowed_data_damage_level = 1.0;
 
-    dataragon::split(text, allowed_data_damage_level, count, threshold).and_then(|(shares, secret_box)| {
-        let encoded_secret_box: heapless::Vec<u8, 16384> = postcard::to_vec(&secret_box).unwrap();
-        return serialization::add_ecc_and_crc(encoded_secret_box.to_vec(), allowed_data_damage_level).map(|encoded_secret_box_with_ecc_and_crc| {
-            println!("Shares: {:?}", shares.map(|s| bs58::encode(s).into_string()));
+    dataragon::split_with_fingerprint(text, count, threshold).and_then(|(shares, secret_box, fingerprint)| {
+        let shares = Zeroizing::new(shares);
+        let encoded_secret_box = serialization::to_bytes(&secret_box)?;
+        let plan = ecc_plan(encoded_secret_box.len(), profile, allowed_data_damage_level)?;
+        let fingerprint = json::to_hex(&fingerprint);
+        let encoded_secret_box_with_ecc_and_crc = serialization::add_ecc_and_crc_with_plan(encoded_secret_box, &plan)?;
+        if let Some(out_dir) = out_dir {
+            let document = split_document(shares.as_slice(), &secret_box, encoded_secret_box_with_ecc_and_crc.as_slice(), &plan, threshold, count, fingerprint.as_str())?;
+            match write_share_files(out_dir.as_str(), holders, &document) {
+                Ok(paths) => paths.iter().for_each(|path| println!("{}", path.display())),
+                Err(e) => {
+                    eprintln!("{}", e);
+                    std::process::exit(1);
+                }
+            }
+        } else if json {
+            let document = split_document(shares.as_slice(), &secret_box, encoded_secret_box_with_ecc_and_crc.as_slice(), &plan, threshold, count, fingerprint.as_str())?;
+            println!("{}", serde_json::to_string_pretty(&document).unwrap());
+        } else if paper {
+            for (i, share) in shares.iter().enumerate() {
+                println!("Share {}:\n{}\n", i + 1, paper::encode_lines(share.as_slice()));
+            }
+            println!("Encrypted box:\n{}\n", paper::encode_lines(encoded_secret_box_with_ecc_and_crc.as_slice()));
+            println!("Fingerprint: {}", fingerprint);
+        } else {
+            let encoded_shares = Zeroizing::new(shares.iter().map(|s| bs58::encode(s).into_string()).collect::<Vec<String>>());
+            println!("Shares: {:?}", *encoded_shares);
             println!("Encrypted box: {:?}", bs58::encode(encoded_secret_box_with_ecc_and_crc).into_string());
-        });
-    }).unwrap();
+            println!("Fingerprint: {}", fingerprint);
+        }
+        Ok(())
+    }).unwrap_or_else(|e| {
+        eprintln!("Can't split the secret: {}", e);
+        std::process::exit(1);
+    });
+}
+
+fn is_valid_secretbox(text: &str) -> bool {
+    encoding::decode_base58_with_erasures(text)
+        .and_then(|(secretbox, erasures)| serialization::try_to_read_stored_data_with_erasures(secretbox.as_slice(), erasures.as_slice()))
+        .is_ok()
+}
+
+/// The legacy shares have no checksum, so they're always valid
+fn is_valid_share(text: &str) -> bool {
+    encoding::decode_base58_with_erasures(text)
+        .and_then(|(share, erasures)| dataragon::decode_share_with_erasures(share.as_slice(), erasures.as_slice()))
+        .is_ok()
+}
+
+/// The share with a checksum, a mistyped legacy share can't be told from the valid one
+fn is_checked_share(text: &str) -> bool {
+    encoding::decode_base58_with_erasures(text)
+        .and_then(|(share, erasures)| dataragon::decode_share_with_erasures(share.as_slice(), erasures.as_slice()))
+        .map(|share| !share.is_legacy())
+        .unwrap_or(false)
+}
+
+/// Repairs the share with a checksum, the legacy shares are kept as they are
+fn repair_share(text: String) -> String {
+    if is_valid_share(text.as_str()) && !is_checked_share(text.as_str()) {
+        eprintln!("The legacy share has no checksum, so its typos can't be found: {}", text);
+        return text;
+    }
+    repair_typos("share", text, is_checked_share)
+}
+
+/// Shows the repaired text if it has typos
+fn repair_typos(name: &str, text: String, is_valid: fn(&str) -> bool) -> String {
+    if is_valid(text.as_str()) {
+        return text;
+    }
+    match encoding::repair_typos(text.as_str(), &encoding::RepairLimits::default(), is_valid) {
+        Some(repaired) => {
+            eprintln!("The {} is mistyped, the repaired one: {}", name, repaired);
+            repaired
+        }
+        None => {
+            eprintln!("The {} can't be repaired: {}", name, text);
+            text
+        }
+    }
+}
+
+/// Decodes the base58 text or exits if it has the characters out of the alphabet
+fn decode_base58(name: &str, text: &str) -> (Vec<u8>, Vec<usize>) {
+    encoding::decode_base58_with_erasures(text).unwrap_or_else(|e| {
+        eprintln!("The {} can't be decoded, try --repair for the typos: {}\n{}", name, e, text);
+        std::process::exit(1);
+    })
+}
+
+/// Reads the lines from the file and shows the damaged ones
+fn read_paper(name: &str, path: &str) -> Result<paper::Transcription, String> {
+    let text = std::fs::read_to_string(path).map_err(|e| format!("Can't read {}: {}", path, e))?;
+    let transcription = paper::decode_lines(text.as_str()).map_err(|e| format!("Can't read the {} from {}: {}", name, path, e))?;
+    if !transcription.damaged_lines.is_empty() {
+        eprintln!("The lines {:?} of the {} are missing or mistyped", transcription.damaged_lines, name);
+    }
+    Ok(transcription)
+}
+
+/// The secret isn't a text which can be shown: it's not UTF-8 or it has control characters
+fn is_binary(secret: &[u8]) -> bool {
+    match std::str::from_utf8(secret) {
+        Ok(text) => text.chars().any(|c| c.is_control() && !matches!(c, '\n' | '\r' | '\t')),
+        Err(_) => true,
+    }
+}
+
+fn format_secret(secret: Vec<u8>, format: OutputFormat) -> Result<Vec<u8>, String> {
+    match format {
+        OutputFormat::Raw => Ok(secret),
+        OutputFormat::Hex => Ok(format!("{}\n", json::to_hex(secret.as_slice())).into_bytes()),
+        OutputFormat::Base64 => Ok(format!("{}\n", base64::engine::general_purpose::STANDARD.encode(secret)).into_bytes()),
+        OutputFormat::Utf8 => String::from_utf8(secret)
+            .map(|text| text.into_bytes())
+            .map_err(|_| "The secret isn't UTF-8, use --format raw, hex or base64".to_string()),
+    }
+}
+
+/// Writes the secret to the file, the file descriptor or stdout.
+/// The binary secret isn't written to the terminal without `force`, it can break the terminal or stay in its history.
+fn write_secret(secret: Vec<u8>, format: OutputFormat, output: Option<String>, output_fd: Option<i32>, force: bool) -> Result<(), String> {
+    let binary = matches!(format, OutputFormat::Raw | OutputFormat::Utf8) && is_binary(secret.as_slice());
+    if !binary && secret.last().map(|b| b.is_ascii_whitespace()).unwrap_or(false) {
+        eprintln!("The secret ends with whitespace");
+    }
+    let formatted = format_secret(secret, format)?;
+    match (output, output_fd) {
+        (Some(file), _) => std::fs::write(file.as_str(), formatted).map_err(|e| format!("Can't write {}: {}", file, e)),
+        (None, Some(fd)) => file_from_fd(fd).and_then(|mut file| file.write_all(formatted.as_slice())).map_err(|e| format!("Can't write the output file descriptor: {}", e)),
+        (None, None) => write_to_stdout(&mut std::io::stdout(), std::io::stdout().is_terminal(), formatted.as_slice(), binary, force),
+    }.map(|_| if binary {
+        eprintln!("The secret is binary");
+    })
+}
+
+/// The binary secret isn't written to the terminal without `force`
+fn write_to_stdout<W: Write>(stdout: &mut W, is_terminal: bool, formatted: &[u8], binary: bool, force: bool) -> Result<(), String> {
+    if binary && is_terminal && !force {
+        return Err("The secret is binary, use --output, --format hex or base64, or --force to write it to the terminal".to_string());
+    }
+    stdout.write_all(formatted).map_err(|e| format!("Can't write stdout: {}", e))
+}
+
+/// Reads the JSON document from the file or stdin
+fn read_document(path: &str) -> Result<json::InputDocument, String> {
+    let text = if path == "-" {
+        let mut text = String::new();
+        std::io::stdin().read_to_string(&mut text).map_err(|e| format!("Can't read stdin: {}", e))?;
+        text
+    } else {
+        std::fs::read_to_string(path).map_err(|e| format!("Can't read {}: {}", path, e))?
+    };
+    let document: json::InputDocument = serde_json::from_str(text.as_str())
+        .map_err(|e| format!("Can't parse the JSON document {}: {}", path, e))?;
+    document.check_version().map_err(|e| format!("Can't read {}: {}", path, e))?;
+    Ok(document)
+}
+
+/// Reads the share file written by `split --out-dir`
+fn read_share_file(path: &str) -> Result<json::ShareDocument, String> {
+    let text = std::fs::read_to_string(path).map_err(|e| format!("Can't read {}: {}", path, e))?;
+    let share: json::ShareDocument = serde_json::from_str(text.as_str())
+        .map_err(|e| format!("Can't parse the share file {}: {}", path, e))?;
+    share.check_version().map_err(|e| format!("Can't read {}: {}", path, e))?;
+    Ok(share)
+}
+
+/// Reads the base58 encrypted box from the text file
+fn read_secretbox_file(path: &str) -> Result<String, String> {
+    std::fs::read_to_string(path)
+        .map(|text| text.trim().to_string())
+        .map_err(|e| format!("Can't read {}: {}", path, e))
+}
+
+fn read_drill_record(path: &str) -> Result<json::DrillRecord, String> {
+    let text = std::fs::read_to_string(path).map_err(|e| format!("Can't read {}: {}", path, e))?;
+    let record: json::DrillRecord = serde_json::from_str(text.as_str())
+        .map_err(|e| format!("Can't parse the drill record {}: {}", path, e))?;
+    record.check_version().map_err(|e| format!("Can't read {}: {}", path, e))?;
+    Ok(record)
 }
 
-fn combine(shares: Vec<String>, secretbox_string: String) {
-    let secretbox = bs58::decode(secretbox_string).into_vec();
-    let sb = serialization::try_to_read_stored_data(secretbox.unwrap().as_slice()).unwrap();
+/// Recovers the secret without writing it, shows the combined shares, compares the secret fingerprint
+/// and checks the drill record written before
+fn combine_drill(shares: &[Share], secret_box: &CryptoSecretbox, expected_fingerprint: Option<String>, record: Option<String>, verify_record: Option<String>) {
+    let drill = dataragon::drill_shares(shares, secret_box).unwrap_or_else(|e| {
+        eprintln!("The secret isn't recoverable: {}", e);
+        std::process::exit(1);
+    });
+    let fingerprint = json::to_hex(&drill.fingerprint);
+    println!("recoverable with shares {{{}}}", drill.indices.iter().join(","));
+    let fingerprint_matches = expected_fingerprint.map(|expected| expected.trim().eq_ignore_ascii_case(fingerprint.as_str()));
+    match fingerprint_matches {
+        Some(true) => println!("The secret fingerprint matches"),
+        Some(false) => eprintln!("The secret fingerprint {} differs from the stored one", fingerprint),
+        None => eprintln!("There's no stored fingerprint to compare, the secret fingerprint is {}", fingerprint),
+    }
+    let record_is_authentic = verify_record.map(|path| {
+        let previous = or_exit(read_drill_record(path.as_str()));
+        let tag = json::to_hex(&drill.authenticate(previous.authenticated_bytes().as_slice()));
+        let authentic = previous.set_id == json::to_hex(&secret_box.set_id()) && previous.tag.eq_ignore_ascii_case(tag.as_str());
+        if authentic {
+            println!("The drill record {} is authentic: {}, shares {{{}}}", path, previous.created, previous.shares.iter().join(","));
+        } else {
+            eprintln!("The drill record {} isn't made by the shares of this set or it's changed", path);
+        }
+        authentic
+    });
+    if let Some(path) = record {
+        let mut drill_record = json::DrillRecord {
+            version: json::DOCUMENT_VERSION,
+            created: created(),
+            set_id: json::to_hex(&secret_box.set_id()),
+            shares: drill.indices.clone(),
+            fingerprint,
+            fingerprint_matches,
+            tag: String::new(),
+        };
+        drill_record.tag = json::to_hex(&drill.authenticate(drill_record.authenticated_bytes().as_slice()));
+        if let Err(e) = write_new_file(Path::new(path.as_str()), serde_json::to_string_pretty(&drill_record).unwrap().as_bytes()) {
+            eprintln!("Can't write {}: {}", path, e);
+            std::process::exit(1);
+        }
+    }
+    if fingerprint_matches == Some(false) || record_is_authentic == Some(false) {
+        std::process::exit(1);
+    }
+}
+
+fn combine(shares: Vec<String>, shares_paper: Vec<String>, json: Vec<String>, share_files: Vec<String>, secretbox_strings: Vec<String>, secretbox_paper: Vec<String>,
+           secretbox_files: Vec<String>, repair: bool, output: Option<String>, output_fd: Option<i32>, format: OutputFormat, force: bool,
+           drill: bool, fingerprint: Option<String>, record: Option<String>, verify_record: Option<String>) {
+    let documents: Vec<json::InputDocument> = or_exit(json.iter().map(|path| read_document(path.as_str()))
+        .chain(share_files.iter().map(|path| read_share_file(path.as_str()).map(json::InputDocument::Share)))
+        .collect());
+    let shares: Zeroizing<Vec<String>> = Zeroizing::new(shares.into_iter()
+        .chain(documents.iter().flat_map(|d| d.shares()).map(|share| share.share.clone()))
+        .collect());
+    let secretbox_strings: Vec<String> = secretbox_strings.into_iter()
+        .chain(documents.iter().filter_map(|d| d.secretbox()).map(|secretbox| secretbox.to_string()))
+        .chain(or_exit(secretbox_files.iter().map(|path| read_secretbox_file(path.as_str())).collect::<Result<Vec<_>, _>>()))
+        .collect();
+    let (shares, secretbox_strings) = if repair {
+        (Zeroizing::new(shares.iter().cloned().map(repair_share).collect()),
+         secretbox_strings.into_iter().map(|s| repair_typos("encrypted box", s, is_valid_secretbox)).collect())
+    } else {
+        (shares, secretbox_strings)
+    };
+    let secretboxes: Vec<(Vec<u8>, Vec<usize>)> = secretbox_strings.iter()
+        .map(|s| decode_base58("encrypted box", s.as_str()))
+        .chain(secretbox_paper.iter().map(|path| {
+            let transcription = or_exit(read_paper("encrypted box", path.as_str()));
+            (transcription.data, transcription.erasures)
+        }))
+        .collect();
+    let copies: Vec<(&[u8], &[usize])> = secretboxes.iter().map(|(data, erasures)| (data.as_slice(), erasures.as_slice())).collect();
+    let (sb, report) = serialization::try_to_read_stored_data_from_copies(copies.as_slice()).unwrap_or_else(|e| {
+        eprintln!("The encrypted box can't be read: {}", e);
+        std::process::exit(1);
+    });
+    eprintln!("Encrypted box:\n{}", report);
     let secret_box_bytes = sb.as_slice();
-    let secret_box: CryptoSecretbox = postcard::from_bytes(&secret_box_bytes).unwrap();
-    dataragon::combine(shares.map(|s| bs58::decode(s).into_vec().unwrap()), &secret_box).map(|r| {
-        println!("Result: '{}'", String::from_utf8(r).unwrap());
-    }).unwrap();
+    let secret_box: CryptoSecretbox = postcard::from_bytes(&secret_box_bytes).unwrap_or_else(|e| {
+        eprintln!("The encrypted box is read, but it isn't an encrypted box: {}", e);
+        std::process::exit(1);
+    });
+    let shares: Zeroizing<Vec<Share>> = Zeroizing::new(shares.iter()
+        .map(|s| decode_base58("share", s.as_str()))
+        .chain(shares_paper.iter().map(|path| {
+            let transcription = or_exit(read_paper("share", path.as_str()));
+            (transcription.data, transcription.erasures)
+        }))
+        .map(|(share, erasures)| {
+            let share = Zeroizing::new(share);
+            dataragon::decode_share_with_erasures(share.as_slice(), erasures.as_slice())
+                .unwrap_or_else(|e| {
+                    eprintln!("The share can't be read, try --repair for the typos: {}", e);
+                    std::process::exit(1);
+                })
+        })
+        .collect());
+    if drill {
+        let expected_fingerprint = fingerprint.or_else(|| documents.iter().find_map(|d| d.fingerprint()).map(|f| f.to_string()));
+        return combine_drill(shares.as_slice(), &secret_box, expected_fingerprint, record, verify_record);
+    }
+    let result = dataragon::combine_shares(shares.as_slice(), &secret_box)
+        .map_err(|e| format!("The secret can't be recovered: {}", e))
+        .and_then(|secret| write_secret(secret, format, output, output_fd, force));
+    if let Err(e) = result {
+        eprintln!("{}", e);
+        std::process::exit(1);
+    }
+}
+
+/// Recovers every set found in the directory which has reached its threshold
+fn combine_from_dir(dir: String, output: Option<String>, output_fd: Option<i32>, format: OutputFormat, force: bool) {
+    let found = discovery::discover(Path::new(dir.as_str()));
+    found.warnings.iter().for_each(|warning| eprintln!("{}", warning));
+    found.sets.iter().for_each(|set| eprintln!("{}", set));
+    let recoverable: Vec<&discovery::SetGroup> = found.sets.iter().filter(|set| set.is_recoverable()).collect();
+    if recoverable.is_empty() {
+        eprintln!("No set has reached its threshold");
+        std::process::exit(1);
+    }
+    if recoverable.len() > 1 && output_fd.is_some() {
+        eprintln!("{} sets are recoverable, but the file descriptor takes one secret, use --output to write them to the files", recoverable.len());
+        std::process::exit(1);
+    }
+    if recoverable.len() > 1 && output.is_none() {
+        eprintln!("{} sets are recoverable, use --output to write them to the files", recoverable.len());
+        std::process::exit(1);
+    }
+    let mut failed = false;
+    for set in recoverable.iter() {
+        let set_output = match &output {
+            Some(output) if recoverable.iter().filter(|other| other.set_id == set.set_id).count() > 1 =>
+                Some(format!("{}.{}.v{}", output, json::to_hex(&set.set_id), set.version)),
+            Some(output) if recoverable.len() > 1 => Some(format!("{}.{}", output, json::to_hex(&set.set_id))),
+            output => output.clone(),
+        };
+        let result = dataragon::combine_shares(set.shares.as_slice(), set.secret_box.as_ref().unwrap())
+            .map_err(|e| e.to_string())
+            .and_then(|secret| write_secret(secret, format, set_output.clone(), output_fd, force));
+        match result {
+            Ok(_) => if let Some(set_output) = set_output {
+                eprintln!("The set {} is written to {}", json::to_hex(&set.set_id), set_output);
+            },
+            Err(e) => {
+                eprintln!("The set {} isn't recovered: {}", json::to_hex(&set.set_id), e);
+                failed = true;
+            }
+        }
+    }
+    if failed {
+        std::process::exit(1);
+    }
+}
+
+fn migrate(shares: Vec<String>, secretbox_string: String, count: u8, threshold: u8, profile: Option<MediaProfile>, repair: bool) {
+    let (shares, secretbox_string) = if repair {
+        (Zeroizing::new(shares.into_iter().map(repair_share).collect::<Vec<String>>()), repair_typos("encrypted box", secretbox_string, is_valid_secretbox))
+    } else {
+        (Zeroizing::new(shares), secretbox_string)
+    };
+    let (secretbox, erasures) = decode_base58("encrypted box", secretbox_string.as_str());
+    let (encoded_secret_box, migrated_secret_box) = serialization::migrate_stored_data_with_plan(secretbox.as_slice(), erasures.as_slice(), |len| ecc_plan(len, profile, 1.0))
+        .unwrap_or_else(|e| {
+            eprintln!("The encrypted box can't be read: {}", e);
+            std::process::exit(1);
+        });
+    let secret_box: CryptoSecretbox = postcard::from_bytes(encoded_secret_box.as_slice()).unwrap_or_else(|e| {
+        eprintln!("The encrypted box is read, but it isn't an encrypted box: {}", e);
+        std::process::exit(1);
+    });
+    let migrated_shares: Zeroizing<Vec<String>> = Zeroizing::new(shares.iter()
+        .map(|s| {
+            let (share, erasures) = decode_base58("share", s.as_str());
+            let share = Zeroizing::new(share);
+            dataragon::migrate_share_with_erasures(share.as_slice(), erasures.as_slice(), &secret_box, threshold, count)
+                .map(|migrated| bs58::encode(Zeroizing::new(migrated).as_slice()).into_string())
+                .unwrap_or_else(|e| {
+                    eprintln!("The share can't be migrated, try --repair for the typos: {}\n{}", e, s);
+                    std::process::exit(1);
+                })
+        })
+        .collect());
+    for (i, share) in migrated_shares.iter().enumerate() {
+        println!("Share {}: {}", i + 1, share);
+    }
+    println!("Encrypted box: {}", bs58::encode(migrated_secret_box).into_string());
+}
+
+fn plan(size: usize, random: Option<usize>, burst: Option<usize>, profile: Option<MediaProfile>, level: Option<f32>) {
+    let plan = match level {
+        Some(level) => Ok(EccPlan::from_damage_level(size, level)),
+        None => {
+            let mut target = profile.map(|p| p.damage_target()).unwrap_or_default();
+            target.random_bytes = random.unwrap_or(target.random_bytes);
+            target.burst_bytes = burst.unwrap_or(target.burst_bytes);
+            planner::plan(size, &target)
+        }
+    };
+    let (plan, encoded_len) = plan.and_then(|plan| plan.encoded_len().map(|encoded_len| (plan, encoded_len)))
+        .unwrap_or_else(|e| {
+            eprintln!("Can't plan the error-correcting code: {}", e);
+            std::process::exit(1);
+        });
+    let guaranteed_random_damage = plan.guaranteed_random_damage();
+    println!("Reed-Solomon ECC: {} bytes", plan.reed_solomon_ecc_len);
+    println!("Plain copies: {}", plan.copies);
+    println!("Header copies: {}", plan.header_copies);
+    println!("Header Reed-Solomon ECC: {} bytes", plan.header_reed_solomon_ecc_len);
+    println!("Stored size: {} bytes (overhead {:.1}x)", encoded_len, encoded_len as f32 / size as f32);
+    println!("Guaranteed to survive {} damaged bytes in any positions ({:.1}% of the stored size) or a burst of {} bytes",
+             guaranteed_random_damage, guaranteed_random_damage as f32 * 100.0 / encoded_len as f32, plan.guaranteed_burst_damage());
+}
+
+fn armor(file: String, profile: Option<MediaProfile>, level: Option<f32>, encoding: TextEncoding) -> Result<(), String> {
+    let data = std::fs::read(file.as_str()).map_err(|e| format!("Can't read {}: {}", file, e))?;
+    let text = ecc_plan(data.len(), profile, level.unwrap_or(1.0))
+        .and_then(|plan| dataragon::armor(data.as_slice(), &plan, encoding))
+        .map_err(|e| format!("Can't armor {}: {}", file, e))?;
+    println!("{}", text);
+    Ok(())
+}
+
+fn unarmor(file: Option<String>, encoding: TextEncoding, output: Option<String>) -> Result<(), String> {
+    let text = match file {
+        Some(file) => std::fs::read_to_string(file.as_str()).map_err(|e| format!("Can't read {}: {}", file, e))?,
+        None => {
+            let mut text = String::new();
+            std::io::stdin().read_to_string(&mut text).map_err(|e| format!("Can't read stdin: {}", e))?;
+            text
+        }
+    };
+    let (data, report) = dataragon::unarmor(text.as_str(), encoding).map_err(|e| format!("The armored data can't be restored: {}", e))?;
+    // the report doesn't get mixed with the restored bytes in stdout
+    eprintln!("{}", report);
+    match output {
+        Some(output) => std::fs::write(output.as_str(), data).map_err(|e| format!("Can't write {}: {}", output, e)),
+        None => std::io::stdout().write_all(data.as_slice()).map_err(|e| format!("Can't write stdout: {}", e)),
+    }
+}
+
+/// The value, otherwise the error is shown and the process exits with 1
+fn or_exit<T>(result: Result<T, String>) -> T {
+    result.unwrap_or_else(|e| {
+        eprintln!("{}", e);
+        std::process::exit(1);
+    })
+}
+
+/// How an artifact was read by `verify`, in the order of the exit codes
+#[derive(Eq, PartialEq, Ord, PartialOrd, Debug, Copy, Clone)]
+enum Health {
+    Fine,
+    Damaged,
+    Unreadable,
+}
+
+/// Reads the stored data and shows what it is and how far it is from being lost.
+/// The margin is the number of the errors the ECC could correct additionally, if the data is restored by the ECC.
+/// The data restored by the ECC is damaged if its margin is less than `min_margin`.
+fn verify_stored_data(name: &str, data: &[u8], erasures: &[usize], min_margin: usize) -> (Health, Option<discovery::Artifact>, Option<usize>) {
+    match serialization::try_to_read_stored_data_with_report(data, erasures) {
+        Ok((payload, report)) => {
+            let artifact = discovery::classify_payload(payload.as_slice());
+            match &artifact {
+                Some(artifact) => println!("{}: {}", name, artifact),
+                None => println!("{}: stored data which isn't a share or an encrypted box", name),
+            }
+            println!("{}\n", report);
+            let margin = report.repair.map(|repair| repair.margin);
+            let headers_disagree = report.agreeing_header_copies * 2 <= report.header_copies;
+            let health = match margin {
+                _ if headers_disagree => Health::Damaged,
+                Some(margin) if report.intact_plain_copies == 0 && margin < min_margin => Health::Damaged,
+                _ => Health::Fine,
+            };
+            (health, artifact, margin)
+        }
+        Err(_) if discovery::is_legacy_share(data, erasures) => {
+            println!("{}: {}, migrate it to check it\n", name, discovery::Artifact::LegacyShare);
+            (Health::Damaged, Some(discovery::Artifact::LegacyShare), None)
+        }
+        Err(e) => {
+            println!("{}: can't be read: {}\n", name, e);
+            (Health::Unreadable, None, None)
+        }
+    }
+}
+
+/// The words found in the files can be anything, they're stored data if its header or the legacy share is found
+fn is_stored_data(data: &[u8], erasures: &[usize]) -> bool {
+    discovery::is_legacy_share(data, erasures) || serialization::inspect_stored_data(data).is_ok()
+        || serialization::try_to_read_stored_data_with_erasures(data, erasures).is_ok()
+}
+
+/// Reads every share and encrypted box and checks the shares of the same set agree with each other,
+/// and belong to one of the encrypted boxes if any box is checked.
+/// The secret isn't recovered, so the shares of a set can be checked one by one on different machines.
+/// The files without the shares and the encrypted boxes are only reported, but nothing to check is an error.
+fn verify(files: Vec<String>, shares: Vec<String>, secretboxes: Vec<String>, min_margin: usize) {
+    let mut warnings = Vec::new();
+    let mut notes = Vec::new();
+    let paths: Vec<PathBuf> = files.iter()
+        .flat_map(|file| {
+            let path = PathBuf::from(file);
+            if path.is_dir() { discovery::files_in(path.as_path(), &mut warnings) } else { vec![path] }
+        })
+        .collect();
+    let mut stored_data: Vec<(String, Vec<u8>, Vec<usize>)> = Vec::new();
+    for path in paths {
+        match std::fs::read(path.as_path()) {
+            Ok(bytes) => {
+                let found: Vec<(Vec<u8>, Vec<usize>)> = discovery::stored_data_in_file(bytes.as_slice()).into_iter()
+                    .filter(|(data, erasures)| is_stored_data(data.as_slice(), erasures.as_slice()))
+                    .collect();
+                if found.is_empty() {
+                    notes.push(format!("No shares or encrypted boxes in {}", path.display()));
+                }
+                let several = found.len() > 1;
+                stored_data.extend(found.into_iter().enumerate().map(|(i, (data, erasures))| {
+                    let name = if several { format!("{} #{}", path.display(), i + 1) } else { path.display().to_string() };
+                    (name, data, erasures)
+                }));
+            }
+            Err(e) => warnings.push(format!("Can't read {}: {}", path.display(), e)),
+        }
+    }
+    let arguments = shares.iter().map(|s| ("share", s)).chain(secretboxes.iter().map(|s| ("encrypted box", s)));
+    for (i, (kind, text)) in arguments.enumerate() {
+        let name = format!("{} argument {}", kind, i + 1);
+        match encoding::decode_base58_with_erasures(text.as_str()) {
+            Ok((data, erasures)) => stored_data.push((name, data, erasures)),
+            Err(e) => warnings.push(format!("The {} isn't base58: {}", name, e)),
+        }
+    }
+    if stored_data.is_empty() {
+        warnings.push("No shares or encrypted boxes to check".to_string());
+    }
+
+    let mut health = if warnings.is_empty() { Health::Fine } else { Health::Unreadable };
+    let mut set_information: HashMap<[u8; SET_ID_LENGTH], HashSet<(u8, u8)>> = HashMap::new();
+    let mut checked_shares: Vec<(&str, Share)> = Vec::new();
+    let mut box_set_ids: Vec<[u8; SET_ID_LENGTH]> = Vec::new();
+    let mut least_margin: Option<(usize, &str)> = None;
+    for (name, data, erasures) in stored_data.iter() {
+        let (artifact_health, artifact, margin) = verify_stored_data(name.as_str(), data.as_slice(), erasures.as_slice(), min_margin);
+        health = health.max(artifact_health);
+        if let Some(margin) = margin.filter(|margin| least_margin.map_or(true, |(least, _)| *margin < least)) {
+            least_margin = Some((margin, name.as_str()));
+        }
+        match artifact {
+            Some(discovery::Artifact::Share(share)) => {
+                set_information.entry(share.set_id).or_default().insert((share.threshold, share.count));
+                checked_shares.push((name.as_str(), share));
+            }
+            Some(discovery::Artifact::Secretbox(secret_box)) => box_set_ids.push(secret_box.set_id()),
+            _ => (),
+        }
+    }
+    for (set_id, information) in set_information.iter() {
+        if information.len() > 1 {
+            warnings.push(format!("The shares of the set {} have different thresholds or counts", json::to_hex(set_id)));
+            health = health.max(Health::Damaged);
+        }
+    }
+    if !box_set_ids.is_empty() {
+        for (name, share) in checked_shares.iter().filter(|(_, share)| !box_set_ids.contains(&share.set_id)) {
+            warnings.push(format!("{}: the share of the set {} doesn't belong to any of the encrypted boxes", name, json::to_hex(&share.set_id)));
+            health = health.max(Health::Unreadable);
+        }
+    }
+    notes.iter().chain(warnings.iter()).for_each(|warning| eprintln!("{}", warning));
+    match health {
+        Health::Fine => println!("All {} artifacts are fine", stored_data.len()),
+        Health::Damaged => println!("Some artifacts are damaged, please make new copies of them"),
+        Health::Unreadable => println!("Some artifacts can't be read"),
+    }
+    if let Some((margin, name)) = least_margin {
+        println!("The closest to be lost is {}, the ECC can correct {} more errors in it", name, margin);
+    }
+    std::process::exit(health as i32);
+}
+
+/// The information of the JSON document which isn't in the stored data
+fn show_document(document: &json::InputDocument) {
+    match document {
+        json::InputDocument::Split(split) => {
+            println!("JSON document of split, version {}", split.version);
+            println!("Set {}: {} shares, {} needed", split.set_id, split.count, split.threshold);
+            println!("Plan: {} Reed-Solomon ECC bytes, {} copies, {} header copies", split.plan.reed_solomon_ecc_len, split.plan.copies, split.plan.header_copies);
+        }
+        json::InputDocument::Share(share) => {
+            println!("Share file of the share {} of the set {}", share.index, share.set_id);
+            share.holder.iter().for_each(|holder| println!("Holder: {}", holder));
+            share.created.iter().for_each(|created| println!("Created: {}", created));
+        }
+    }
+    document.fingerprint().iter().for_each(|fingerprint| println!("Fingerprint: {}", fingerprint));
+}
+
+/// Shows the stored data layout and what's read from it
+fn inspect_stored_data(name: &str, data: &[u8], erasures: &[usize]) {
+    println!("\n{}: {} bytes, {} known damaged positions", name, data.len(), erasures.len());
+    match serialization::inspect_stored_data(data) {
+        Ok(layout) => println!("{}", layout),
+        Err(e) => println!("The header can't be read: {}", e),
+    }
+    match serialization::try_to_read_stored_data_with_report(data, erasures) {
+        Ok((payload, _)) => match discovery::classify_payload(payload.as_slice()) {
+            Some(discovery::Artifact::Secretbox(secret_box)) => println!("Encrypted box of the set {}: {} bytes of ciphertext, {} bytes of tag",
+                                                                         json::to_hex(&secret_box.set_id()), secret_box.ciphertext.len(), secret_box.tag.len()),
+            Some(artifact) => println!("{}", artifact),
+            None => println!("Stored data of {} bytes which isn't a share or an encrypted box", payload.len()),
+        },
+        Err(_) if discovery::is_legacy_share(data, erasures) => println!("{}", discovery::Artifact::LegacyShare),
+        Err(e) => println!("The data can't be restored: {}", e),
+    }
+}
+
+/// Detects the encoding of the string or the file and shows everything found in it
+fn inspect(artifact: String) {
+    let bytes = match std::fs::read(artifact.as_str()) {
+        Ok(bytes) => bytes,
+        Err(_) if !Path::new(artifact.as_str()).exists() => artifact.trim().as_bytes().to_vec(),
+        Err(e) => {
+            eprintln!("Can't read {}: {}", artifact, e);
+            std::process::exit(1);
+        }
+    };
+    let encoding = match std::str::from_utf8(bytes.as_slice()) {
+        Err(_) => "binary",
+        Ok(text) => match serde_json::from_str::<json::InputDocument>(text) {
+            Ok(document) => {
+                show_document(&document);
+                "JSON"
+            }
+            Err(_) if text.lines().any(paper::is_valid_line) => "paper lines",
+            Err(_) => "base58",
+        },
+    };
+    let stored_data = discovery::stored_data_in_file(bytes.as_slice());
+    println!("Encoding: {}, {} stored data found", encoding, stored_data.len());
+    for (i, (data, erasures)) in stored_data.iter().enumerate() {
+        inspect_stored_data(format!("Stored data {}", i + 1).as_str(), data.as_slice(), erasures.as_slice());
+    }
+    if stored_data.is_empty() {
+        std::process::exit(1);
+    }
+}
+
+fn simulate(size: usize, levels: Vec<f32>, damages: Vec<Damage>, trials: usize, seed: u64) {
+    let data: Vec<u8> = (0..size).map(|i| (i as u64).wrapping_mul(2654435761).wrapping_add(seed) as u8).collect();
+    for level in levels {
+        for damage in damages.iter() {
+            match simulator::simulate(data.as_slice(), level, damage, trials, seed) {
+                Ok(result) => println!("Level {}, {}: {} of {} recovered ({:.1}%), {} miscorrected",
+                                       level, damage, result.recovered, result.trials, result.recovery_rate() * 100.0, result.miscorrected),
+                Err(e) => println!("Level {}: {}", level, e),
+            }
+        }
+    }
 }
 
 fn main() {
     match DataragonCommands::from_args() {
-        DataragonCommands::Split { count, threshold } => split(count, threshold),
-        DataragonCommands::Combine { shares, secretbox } => combine(shares, secretbox)
+        DataragonCommands::Split { count, threshold, profile, paper, json, out_dir, holders, input, stdin, input_fd } =>
+            split(count, threshold, profile, paper, json, out_dir, holders, or_exit(read_secret(input, stdin, input_fd))),
+        DataragonCommands::Combine { from_dir: Some(dir), output, output_fd, format, force, .. } =>
+            combine_from_dir(dir, output, output_fd, format, force),
+        DataragonCommands::Combine { shares, shares_paper, json, share_file, secretbox, secretbox_paper, secretbox_file, repair, output, output_fd, format, force, drill, fingerprint, record, verify_record, .. } =>
+            combine(shares, shares_paper, json, share_file, secretbox, secretbox_paper, secretbox_file, repair, output, output_fd, format, force, drill, fingerprint, record, verify_record),
+        DataragonCommands::Migrate { shares, secretbox, count, threshold, profile, repair } => migrate(shares, secretbox, count, threshold, profile, repair),
+        DataragonCommands::Plan { size, random, burst, profile, level } => plan(size, random, burst, profile, level),
+        DataragonCommands::Armor { file, profile, level, encoding } => or_exit(armor(file, profile, level, encoding)),
+        DataragonCommands::Unarmor { file, encoding, output } => or_exit(unarmor(file, encoding, output)),
+        DataragonCommands::Verify { files, shares, secretbox, min_margin } => verify(files, shares, secretbox, min_margin),
+        DataragonCommands::Inspect { artifact } => inspect(artifact),
+        DataragonCommands::Simulate { size, level, damage, trials, seed } => simulate(size, level, damage, trials, seed),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(args: &[&str]) -> std::result::Result<DataragonCommands, structopt::clap::Error> {
+        DataragonCommands::from_iter_safe(std::iter::once("dataragon-cli").chain(args.iter().cloned()))
+    }
+
+    #[test]
+    fn encrypted_box_on_paper_is_enough_to_combine() {
+        assert!(parse(&["combine", "--shares", "a", "--secretbox-paper", "box.txt"]).is_ok());
+        assert!(parse(&["combine", "--shares", "a"]).is_err());
+    }
+
+    #[test]
+    fn file_and_descriptor_conflict() {
+        assert!(parse(&["split", "-c", "3", "-t", "2", "--input", "secret", "--input-fd", "3"]).is_err());
+        assert!(parse(&["split", "-c", "3", "-t", "2", "--stdin", "--input-fd", "3"]).is_err());
+        assert!(parse(&["combine", "--shares", "a", "--secretbox", "b", "--output", "secret", "--output-fd", "3"]).is_err());
+    }
+
+    #[test]
+    fn control_characters_and_invalid_utf8_are_binary() {
+        assert!(!is_binary("password\twith tabs\r\n".as_bytes()));
+        assert!(!is_binary("пароль".as_bytes()));
+        assert!(is_binary("\x1b]0;title\x07".as_bytes()));
+        assert!(is_binary(&[0x70, 0xff, 0x70]));
+    }
+
+    #[test]
+    fn secret_is_formatted() {
+        let secret = vec![0x00, 0x01, 0xff];
+
+        assert_eq!(format_secret(secret.clone(), OutputFormat::Raw), Ok(secret.clone()));
+        assert_eq!(format_secret(secret.clone(), OutputFormat::Hex), Ok(b"0001ff\n".to_vec()));
+        assert_eq!(format_secret(secret.clone(), OutputFormat::Base64), Ok(b"AAH/\n".to_vec()));
+        assert!(format_secret(secret, OutputFormat::Utf8).is_err());
+        assert_eq!(format_secret("текст".as_bytes().to_vec(), OutputFormat::Utf8), Ok("текст".as_bytes().to_vec()));
+    }
+
+    #[test]
+    fn binary_secret_is_refused_on_the_terminal() {
+        let mut terminal = Vec::new();
+        let mut pipe = Vec::new();
+
+        assert!(write_to_stdout(&mut terminal, true, &[0x00, 0xff], true, false).is_err());
+        assert!(terminal.is_empty());
+        assert_eq!(write_to_stdout(&mut terminal, true, &[0x00, 0xff], true, true), Ok(()));
+        assert_eq!(write_to_stdout(&mut pipe, false, &[0x00, 0xff], true, false), Ok(()));
+        assert_eq!(write_to_stdout(&mut terminal, true, b"text", false, false), Ok(()));
+        assert_eq!(terminal, [0x00, 0xff, b't', b'e', b'x', b't']);
+        assert_eq!(pipe, [0x00, 0xff]);
+    }
+
+    #[test]
+    fn secret_is_written_to_the_file() {
+        let path = std::env::temp_dir().join(format!("dataragon-secret-{}", std::process::id()));
+        let output = Some(path.to_string_lossy().to_string());
+
+        let written = write_secret(vec![0x00, 0xff], OutputFormat::Hex, output.clone(), None, false);
+        let utf8 = write_secret(vec![0x00, 0xff], OutputFormat::Utf8, output, None, false);
+
+        assert_eq!(written, Ok(()));
+        assert_eq!(std::fs::read(path.as_path()).unwrap(), b"00ff\n");
+        assert!(utf8.is_err());
+        std::fs::remove_file(path).unwrap();
+    }
+
+    fn split_document_of(shares: &[&str]) -> json::SplitDocument {
+        json::SplitDocument {
+            version: json::DOCUMENT_VERSION,
+            set_id: "00".to_string(),
+            threshold: 2,
+            count: shares.len() as u8,
+            shares: shares.iter().enumerate().map(|(i, share)| json::ShareDocument {
+                version: None,
+                index: i as u8 + 1,
+                set_id: "00".to_string(),
+                threshold: 2,
+                count: shares.len() as u8,
+                share: share.to_string(),
+                holder: None,
+                created: None,
+                instructions: None,
+                fingerprint: None,
+            }).collect(),
+            secretbox: "box".to_string(),
+            format_version: serialization::FORMAT_VERSION,
+            plan: json::PlanDocument::from(&EccPlan::new(10, 0, 1)),
+            fingerprint: None,
+        }
+    }
+
+    /// An empty directory of the test
+    fn test_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("dataragon-{}-{}", name, std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    #[test]
+    fn share_files_are_written_for_the_holders() {
+        let dir = test_dir("share-files");
+        let document = split_document_of(&["a", "b"]);
+
+        let paths = write_share_files(dir.to_str().unwrap(), vec!["Alice".to_string(), " Bob Smith ".to_string()], &document).unwrap();
+
+        assert_eq!(paths, vec![dir.join("Alice.share.json"), dir.join("Bob_Smith.share.json"), dir.join(SECRETBOX_FILE_NAME)]);
+        let share: json::ShareDocument = serde_json::from_str(std::fs::read_to_string(&paths[1]).unwrap().as_str()).unwrap();
+        assert_eq!((share.share.as_str(), share.holder), ("b", Some("Bob Smith".to_string())));
+        assert_eq!(share.version, Some(json::DOCUMENT_VERSION));
+        assert_eq!(std::fs::read_to_string(&paths[2]).unwrap(), "box\n");
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn half_of_the_set_is_not_left() {
+        let dir = test_dir("half-set");
+        std::fs::write(dir.join(SECRETBOX_FILE_NAME), "another box").unwrap();
+
+        let result = write_share_files(dir.to_str().unwrap(), Vec::new(), &split_document_of(&["a", "b"]));
+
+        assert!(result.is_err());
+        let files: Vec<PathBuf> = std::fs::read_dir(&dir).unwrap().map(|entry| entry.unwrap().path()).collect();
+        assert_eq!(files, vec![dir.join(SECRETBOX_FILE_NAME)]);
+        assert_eq!(std::fs::read_to_string(dir.join(SECRETBOX_FILE_NAME)).unwrap(), "another box");
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn holders_must_be_named() {
+        let dir = test_dir("blank-holder");
+        let document = split_document_of(&["a", "b"]);
+
+        assert!(write_share_files(dir.to_str().unwrap(), vec!["Alice".to_string(), " ".to_string()], &document).is_err());
+        assert!(write_share_files(dir.to_str().unwrap(), vec!["Alice".to_string(), "Alice".to_string()], &document).is_err());
+        assert!(write_share_files(dir.to_str().unwrap(), vec!["Alice".to_string()], &document).is_err());
+        assert_eq!(std::fs::read_dir(&dir).unwrap().count(), 0);
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn standard_streams_are_not_taken_as_descriptors() {
+        for fd in 0..=2 {
+            assert!(file_from_fd(fd).is_err());
+        }
     }
 }
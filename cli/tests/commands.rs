@@ -0,0 +1,248 @@
+use std::io::Write;
+use std::path::PathBuf;
+use std::process::{Command, Output, Stdio};
+
+fn run(args: &[&str], stdin: &[u8]) -> Output {
+    let mut child = Command::new(env!("CARGO_BIN_EXE_dataragon-cli"))
+        .args(args)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .unwrap();
+    child.stdin.take().unwrap().write_all(stdin).unwrap();
+    child.wait_with_output().unwrap()
+}
+
+/// An empty directory of the test
+fn test_dir(name: &str) -> PathBuf {
+    let dir = std::env::temp_dir().join(format!("dataragon-{}-{}", name, std::process::id()));
+    let _ = std::fs::remove_dir_all(&dir);
+    std::fs::create_dir_all(&dir).unwrap();
+    dir
+}
+
+#[test]
+fn split_json_is_combined() {
+    let dir = test_dir("split-json");
+    let document = dir.join("split.json");
+
+    let split = run(&["split", "-c", "3", "-t", "2", "--stdin", "--json"], b"multi\nline secret");
+    std::fs::write(&document, split.stdout).unwrap();
+    let combined = run(&["combine", "--json", document.to_str().unwrap()], b"");
+
+    assert!(split.status.success());
+    assert!(combined.status.success(), "{}", String::from_utf8_lossy(&combined.stderr));
+    assert_eq!(combined.stdout, b"multi\nline secret");
+    std::fs::remove_dir_all(dir).unwrap();
+}
+
+#[test]
+fn unreadable_secret_is_an_error() {
+    let missing = run(&["split", "-c", "3", "-t", "2", "--input", "missing-secret.txt"], b"");
+    let standard_stream = run(&["split", "-c", "3", "-t", "2", "--input-fd", "1"], b"");
+
+    for failed in [&missing, &standard_stream].iter() {
+        assert_eq!(failed.status.code(), Some(1));
+        assert!(failed.stdout.is_empty());
+        assert!(!String::from_utf8_lossy(&failed.stderr).contains("panicked"));
+    }
+    assert!(String::from_utf8_lossy(&missing.stderr).contains("Can't read missing-secret.txt"));
+}
+
+#[test]
+fn unreadable_combine_inputs_are_errors() {
+    let dir = test_dir("unreadable-inputs");
+    let garbage = dir.join("garbage.txt");
+    std::fs::write(&garbage, "not a transcription\n").unwrap();
+    let garbage = garbage.to_str().unwrap();
+    let missing = dir.join("missing.json");
+    let missing = missing.to_str().unwrap();
+
+    let failures = vec![
+        run(&["combine", "--json", missing], b""),
+        run(&["combine", "--json", garbage], b""),
+        run(&["combine", "--share-file", missing, "--secretbox", "x"], b""),
+        run(&["combine", "--shares", "x", "--secretbox-file", missing], b""),
+        run(&["combine", "--shares", "x", "--secretbox-paper", garbage], b""),
+    ];
+
+    for failed in failures.iter() {
+        let stderr = String::from_utf8_lossy(&failed.stderr);
+        assert_eq!(failed.status.code(), Some(1), "{}", stderr);
+        assert!(!stderr.contains("panicked"), "{}", stderr);
+    }
+    std::fs::remove_dir_all(dir).unwrap();
+}
+
+#[test]
+fn document_of_unknown_version_is_rejected() {
+    let dir = test_dir("unknown-version");
+    let document = dir.join("split.json");
+
+    let split = run(&["split", "-c", "3", "-t", "2", "--stdin", "--json"], b"secret");
+    let text = String::from_utf8(split.stdout).unwrap().replacen("\"version\": 1", "\"version\": 2", 1);
+    std::fs::write(&document, text).unwrap();
+    let combined = run(&["combine", "--json", document.to_str().unwrap()], b"");
+
+    assert_eq!(combined.status.code(), Some(1));
+    assert!(combined.stdout.is_empty());
+    assert!(String::from_utf8_lossy(&combined.stderr).contains("version 2 isn't supported"));
+    std::fs::remove_dir_all(dir).unwrap();
+}
+
+#[test]
+fn several_sets_are_not_written_to_one_descriptor() {
+    let dir = test_dir("several-sets");
+    for secret in ["first", "second"].iter() {
+        let split = run(&["split", "-c", "3", "-t", "2", "--stdin"], secret.as_bytes());
+        std::fs::write(dir.join(format!("{}.txt", secret)), split.stdout).unwrap();
+    }
+
+    let to_fd = run(&["combine", "--from-dir", dir.to_str().unwrap(), "--output-fd", "3"], b"");
+    let to_files = run(&["combine", "--from-dir", dir.to_str().unwrap(), "--output", dir.join("secret").to_str().unwrap()], b"");
+
+    assert_eq!(to_fd.status.code(), Some(1));
+    assert!(String::from_utf8_lossy(&to_fd.stderr).contains("the file descriptor takes one secret"));
+    assert!(to_files.status.success(), "{}", String::from_utf8_lossy(&to_files.stderr));
+    let mut secrets: Vec<String> = std::fs::read_dir(&dir).unwrap()
+        .map(|entry| entry.unwrap().path())
+        .filter(|path| path.file_name().unwrap().to_str().unwrap().starts_with("secret."))
+        .map(|path| std::fs::read_to_string(path).unwrap())
+        .collect();
+    secrets.sort();
+    assert_eq!(secrets, vec!["first", "second"]);
+    std::fs::remove_dir_all(dir).unwrap();
+}
+
+#[test]
+fn verify_exit_codes() {
+    let dir = test_dir("verify");
+    let split = run(&["split", "-c", "3", "-t", "2", "--stdin"], b"secret");
+    let other = run(&["split", "-c", "3", "-t", "2", "--stdin"], b"other secret");
+    let artifacts = dir.join("split.txt");
+    std::fs::write(&artifacts, split.stdout).unwrap();
+    std::fs::write(dir.join("README.txt"), "The shares of the family secret, fingerprint 0123456789abcdef0123456789abcdef0123456789abcdef").unwrap();
+    let other_share = String::from_utf8(other.stdout).unwrap().split(|c: char| !c.is_ascii_alphanumeric())
+        .find(|word| word.len() > 40).unwrap().to_string();
+    let legacy_share = bs58::encode([7u8; 33]).into_string();
+
+    let fine = run(&["verify", dir.to_str().unwrap()], b"");
+    let legacy = run(&["verify", artifacts.to_str().unwrap(), "--shares", legacy_share.as_str()], b"");
+    let foreign = run(&["verify", artifacts.to_str().unwrap(), "--shares", other_share.as_str()], b"");
+    let unreadable = run(&["verify", "--secretbox", "abc"], b"");
+    let nothing = run(&["verify", dir.join("README.txt").to_str().unwrap()], b"");
+
+    assert_eq!(fine.status.code(), Some(0), "{}", String::from_utf8_lossy(&fine.stderr));
+    assert!(String::from_utf8_lossy(&fine.stderr).contains("No shares or encrypted boxes in"));
+    assert_eq!(legacy.status.code(), Some(1));
+    assert_eq!(foreign.status.code(), Some(2));
+    assert!(String::from_utf8_lossy(&foreign.stderr).contains("doesn't belong to any of the encrypted boxes"));
+    assert_eq!(unreadable.status.code(), Some(2));
+    assert_eq!(nothing.status.code(), Some(2));
+    std::fs::remove_dir_all(dir).unwrap();
+}
+
+#[test]
+fn verify_exit_code_follows_the_margin() {
+    let split = run(&["split", "-c", "3", "-t", "2", "--stdin"], b"secret");
+    let share = String::from_utf8(split.stdout).unwrap().split(|c: char| !c.is_ascii_alphanumeric())
+        .find(|word| word.len() > 40).unwrap().to_string();
+    let mut damaged = bs58::decode(share.as_str()).into_vec().unwrap();
+    // a byte of the only plain copy, the share is restored by Reed-Solomon with 7 more errors correctable
+    damaged[150] ^= 0x55;
+    let damaged = bs58::encode(damaged).into_string();
+
+    let intact = run(&["verify", "--shares", share.as_str(), "--min-margin", "100"], b"");
+    let enough = run(&["verify", "--shares", damaged.as_str(), "--min-margin", "7"], b"");
+    let too_little = run(&["verify", "--shares", damaged.as_str(), "--min-margin", "8"], b"");
+    let default = run(&["verify", "--shares", damaged.as_str()], b"");
+
+    assert_eq!(intact.status.code(), Some(0));
+    assert_eq!(enough.status.code(), Some(0));
+    assert!(String::from_utf8_lossy(&enough.stdout).contains("the ECC can correct 7 more errors"));
+    assert_eq!(too_little.status.code(), Some(1));
+    assert!(String::from_utf8_lossy(&too_little.stdout).contains("please make new copies"));
+    assert_eq!(default.status.code(), Some(0));
+}
+
+#[test]
+fn drill_record_is_verified_by_other_shares() {
+    let dir = test_dir("drill-record");
+    let out_dir = dir.join("shares");
+    let record = dir.join("drill.json");
+    let split = run(&["split", "-c", "3", "-t", "2", "--stdin", "--out-dir", out_dir.to_str().unwrap(), "--holders", "alice,bob,carol"], b"secret");
+    let share = |holder: &str| out_dir.join(format!("{}.share.json", holder)).to_str().unwrap().to_string();
+    let secretbox = out_dir.join("secretbox.txt").to_str().unwrap().to_string();
+
+    let first = run(&["combine", "--share-file", share("alice").as_str(), "--share-file", share("carol").as_str(), "--secretbox-file", secretbox.as_str(),
+                      "--drill", "--record", record.to_str().unwrap()], b"");
+    let second = run(&["combine", "--share-file", share("bob").as_str(), "--share-file", share("carol").as_str(), "--secretbox-file", secretbox.as_str(),
+                       "--drill", "--verify-record", record.to_str().unwrap()], b"");
+    let text = std::fs::read_to_string(&record).unwrap().replace("\"shares\": [\n    1,", "\"shares\": [\n    2,");
+    std::fs::write(&record, text).unwrap();
+    let changed = run(&["combine", "--share-file", share("bob").as_str(), "--share-file", share("carol").as_str(), "--secretbox-file", secretbox.as_str(),
+                        "--drill", "--verify-record", record.to_str().unwrap()], b"");
+
+    assert!(split.status.success());
+    assert!(first.status.success(), "{}", String::from_utf8_lossy(&first.stderr));
+    assert!(second.status.success(), "{}", String::from_utf8_lossy(&second.stderr));
+    assert!(String::from_utf8_lossy(&second.stdout).contains("is authentic"));
+    assert!(String::from_utf8_lossy(&second.stdout).contains("The secret fingerprint matches"));
+    assert_eq!(changed.status.code(), Some(1));
+    std::fs::remove_dir_all(dir).unwrap();
+}
+
+/// The golden vector of the library in base58
+fn golden_vector(hex: &str) -> String {
+    let bytes: Vec<u8> = (0..hex.trim().len()).step_by(2).map(|i| u8::from_str_radix(&hex.trim()[i..i + 2], 16).unwrap()).collect();
+    bs58::encode(bytes).into_string()
+}
+
+#[test]
+fn legacy_golden_vectors_are_migrated() {
+    let legacy_share = golden_vector(include_str!("../../lib/test-vectors/share-v0.hex"));
+    let legacy_stored_data = golden_vector(include_str!("../../lib/test-vectors/stored-data-v0.hex"));
+    let split = String::from_utf8(run(&["split", "-c", "3", "-t", "2", "--stdin"], b"secret").stdout).unwrap();
+    let secretbox = split.lines().find(|line| line.starts_with("Encrypted box: ")).unwrap()
+        .trim_start_matches("Encrypted box: ").trim_matches('"').to_string();
+
+    let migrated = run(&["migrate", "-c", "3", "-t", "2", "--shares", legacy_share.as_str(), "--secretbox", secretbox.as_str()], b"");
+    let not_a_box = run(&["migrate", "-c", "3", "-t", "2", "--shares", legacy_share.as_str(), "--secretbox", legacy_stored_data.as_str()], b"");
+    let mistyped = run(&["migrate", "-c", "3", "-t", "2", "--shares", "0OIl", "--secretbox", secretbox.as_str()], b"");
+
+    assert!(migrated.status.success(), "{}", String::from_utf8_lossy(&migrated.stderr));
+    let stdout = String::from_utf8(migrated.stdout).unwrap();
+    let share = stdout.lines().find(|line| line.starts_with("Share 1: ")).unwrap().trim_start_matches("Share 1: ");
+    let migrated_box = stdout.lines().find(|line| line.starts_with("Encrypted box: ")).unwrap().trim_start_matches("Encrypted box: ");
+    let verified = run(&["verify", "--shares", share, "--secretbox", migrated_box], b"");
+    assert_eq!(verified.status.code(), Some(0), "{}", String::from_utf8_lossy(&verified.stdout));
+    for failed in [&not_a_box, &mistyped].iter() {
+        assert_eq!(failed.status.code(), Some(1));
+        assert!(!String::from_utf8_lossy(&failed.stderr).contains("panicked"));
+    }
+    assert!(String::from_utf8_lossy(&not_a_box.stderr).contains("isn't an encrypted box"));
+}
+
+#[test]
+fn too_damaged_armor_is_an_error() {
+    let dir = test_dir("armor");
+    let file = dir.join("config.txt");
+    std::fs::write(&file, "key = value\n").unwrap();
+    let armored = run(&["armor", file.to_str().unwrap(), "--level", "0.1"], b"");
+    let text = String::from_utf8(armored.stdout).unwrap();
+    let damaged: String = text.trim().chars().enumerate().map(|(i, c)| if i % 3 == 0 { '1' } else { c }).collect();
+
+    let restored = run(&["unarmor"], text.as_bytes());
+    let failed = run(&["unarmor"], damaged.as_bytes());
+    let missing = run(&["armor", dir.join("missing.txt").to_str().unwrap()], b"");
+
+    assert!(armored.status.success());
+    assert_eq!(restored.stdout, b"key = value\n");
+    for failed in [&failed, &missing].iter() {
+        assert_eq!(failed.status.code(), Some(1));
+        assert!(!String::from_utf8_lossy(&failed.stderr).contains("panicked"));
+    }
+    assert!(String::from_utf8_lossy(&failed.stderr).contains("can't be restored"));
+    std::fs::remove_dir_all(dir).unwrap();
+}
@@ -0,0 +1,37 @@
+//! Finds two shifted plain copies in the noise of growing size, the time per byte should stay flat.
+//!
+//! Run with `cargo bench --bench plain_copy_scan`.
+
+use std::time::Instant;
+
+use dataragon::checksum::DEFAULT_CHECKSUM_ALGORITHM;
+use dataragon::objects::{ECCData, Header};
+use dataragon::serialization::{try_to_recover_data_with_ecc, FORMAT_VERSION};
+
+fn noise(len: usize, seed: u64) -> Vec<u8> {
+    (0..len as u64).map(|i| ((i + seed * 1000).wrapping_mul(0x9e3779b97f4a7c15) >> 29) as u8).collect()
+}
+
+fn main() {
+    for size in [1 << 16, 1 << 18, 1 << 20, 1 << 22] {
+        let data = noise(size / 4, 1);
+        let header = Header {
+            version: FORMAT_VERSION,
+            encryption_algorithm: 0,
+            data_len: data.len() as u64,
+            crc_algorithm: DEFAULT_CHECKSUM_ALGORITHM.id(),
+            checksum: DEFAULT_CHECKSUM_ALGORITHM.checksum(data.as_slice()),
+        };
+        // the copies aren't aligned to the start or the end
+        let ecc = [noise(size / 8, 2), data.clone(), noise(size / 4, 3), data.clone(), noise(size / 8, 4)].concat();
+        let copy = ECCData { ecc_algorithm: 0, ecc };
+
+        let start = Instant::now();
+        let recovered = try_to_recover_data_with_ecc(&[], &[], &header, &[(&copy, Vec::new())]);
+        let elapsed = start.elapsed();
+
+        assert_eq!(recovered.map(|(r, _)| r), Some(data));
+        println!("{:>8} bytes: {:>10.3} ms, {:>6.1} ns/byte",
+                 copy.ecc.len(), elapsed.as_secs_f64() * 1e3, elapsed.as_nanos() as f64 / copy.ecc.len() as f64);
+    }
+}
@@ -0,0 +1,94 @@
+use blake2::Blake2b512;
+use crc::{Crc, CRC_16_IBM_SDLC, CRC_32_ISCSI};
+use sha2::{Digest, Sha256, Sha512};
+
+/// The longest checksum which fits in the header
+pub const MAX_CHECKSUM_LENGTH: usize = 8;
+
+const X25: Crc<u16> = Crc::<u16>::new(&CRC_16_IBM_SDLC);
+const CASTAGNOLI: Crc<u32> = Crc::<u32>::new(&CRC_32_ISCSI);
+
+/// The data checksum algorithms, the discriminant is stored in `Header.crc_algorithm`
+#[derive(Eq, PartialEq, Debug, Copy, Clone)]
+pub enum ChecksumAlgorithm {
+    /// CRC-16/X-25 of SHA-512, the only checksum of the format version 0
+    Sha512Crc16 = 0,
+    Crc32c = 1,
+    /// The first 32 bits of SHA-256
+    Sha256x32 = 2,
+    /// The first 64 bits of SHA-256
+    Sha256x64 = 3,
+    /// The first 32 bits of BLAKE2b-512
+    Blake2bx32 = 4,
+    /// The first 64 bits of BLAKE2b-512
+    Blake2bx64 = 5,
+}
+
+/// The checksum of the new stored data.
+/// The plain copies are scanned by windows to find the data, so the checksum should rarely match by chance.
+pub const DEFAULT_CHECKSUM_ALGORITHM: ChecksumAlgorithm = ChecksumAlgorithm::Blake2bx64;
+
+impl ChecksumAlgorithm {
+    pub fn from_id(id: u64) -> Option<ChecksumAlgorithm> {
+        match id {
+            0 => Some(ChecksumAlgorithm::Sha512Crc16),
+            1 => Some(ChecksumAlgorithm::Crc32c),
+            2 => Some(ChecksumAlgorithm::Sha256x32),
+            3 => Some(ChecksumAlgorithm::Sha256x64),
+            4 => Some(ChecksumAlgorithm::Blake2bx32),
+            5 => Some(ChecksumAlgorithm::Blake2bx64),
+            _ => None,
+        }
+    }
+
+    pub fn id(&self) -> u64 {
+        *self as u64
+    }
+
+    /// Length of the checksum in bytes
+    pub fn len(&self) -> usize {
+        match self {
+            ChecksumAlgorithm::Sha512Crc16 => 2,
+            ChecksumAlgorithm::Crc32c | ChecksumAlgorithm::Sha256x32 | ChecksumAlgorithm::Blake2bx32 => 4,
+            ChecksumAlgorithm::Sha256x64 | ChecksumAlgorithm::Blake2bx64 => 8,
+        }
+    }
+
+    /// The checksum padded with zeros to `MAX_CHECKSUM_LENGTH`
+    pub fn checksum(&self, data: &[u8]) -> [u8; MAX_CHECKSUM_LENGTH] {
+        let digest: Vec<u8> = match self {
+            ChecksumAlgorithm::Sha512Crc16 => X25.checksum(Sha512::digest(data).as_slice()).to_be_bytes().to_vec(),
+            ChecksumAlgorithm::Crc32c => CASTAGNOLI.checksum(data).to_be_bytes().to_vec(),
+            ChecksumAlgorithm::Sha256x32 | ChecksumAlgorithm::Sha256x64 => Sha256::digest(data).to_vec(),
+            ChecksumAlgorithm::Blake2bx32 | ChecksumAlgorithm::Blake2bx64 => Blake2b512::digest(data).to_vec(),
+        };
+        let mut checksum = [0; MAX_CHECKSUM_LENGTH];
+        checksum[..self.len()].copy_from_slice(&digest[..self.len()]);
+        checksum
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn checksums_have_their_length() {
+        let data = "1234567890".as_bytes();
+        for id in 0..=5 {
+            let algorithm = ChecksumAlgorithm::from_id(id).unwrap();
+            let checksum = algorithm.checksum(data);
+
+            assert_eq!(algorithm.id(), id);
+            assert!(checksum[algorithm.len()..].iter().all(|b| *b == 0));
+            assert_ne!(checksum, algorithm.checksum("1234567891".as_bytes()));
+        }
+        assert_eq!(ChecksumAlgorithm::from_id(6), None);
+    }
+
+    #[test]
+    fn crc32c_is_castagnoli() {
+        // the check value of CRC-32C
+        assert_eq!(ChecksumAlgorithm::Crc32c.checksum("123456789".as_bytes())[..4], [0xe3, 0x06, 0x92, 0x83]);
+    }
+}
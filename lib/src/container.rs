@@ -0,0 +1,196 @@
+use std::cmp::min;
+use std::collections::HashMap;
+use std::hash::Hash;
+
+use crc::{Crc, CRC_32_ISCSI};
+
+/// Every section starts with the sync marker to find it after the damaged bytes
+pub const SYNC_MARKER: [u8; 4] = [0xda, 0x7a, 0x9d, 0x0e];
+/// The section tag of the header copy, the other tags are ECC algorithms
+pub const HEADER_SECTION: u8 = 0xff;
+/// The section tag of Reed-Solomon ECC of the header
+pub const HEADER_ECC_SECTION: u8 = 0xfe;
+/// The tag and the length are stored three times to restore them by the majority
+const SECTION_FIELDS_COPIES: usize = 3;
+const SECTION_FIELDS_LENGTH: usize = 5;
+/// Marker, the section fields copies and the body checksum
+pub const SECTION_HEADER_LENGTH: usize = SYNC_MARKER.len() + SECTION_FIELDS_COPIES * SECTION_FIELDS_LENGTH + 4;
+
+const CASTAGNOLI: Crc<u32> = Crc::<u32>::new(&CRC_32_ISCSI);
+
+/// The section found in the stored data
+#[derive(PartialEq, Debug, Clone)]
+pub struct Section<'a> {
+    pub tag: u8,
+    /// The body offset in the stored data
+    pub offset: usize,
+    pub body: &'a [u8],
+    /// The body checksum is valid
+    pub intact: bool,
+    /// Number of the body bytes cut by the end of the data
+    pub missing: usize,
+}
+
+/// Appends a section to `out`:
+/// sync marker, 3 copies of tag (u8) and body length (u32 LE), CRC-32C of body (u32 LE), body
+pub fn write_section(out: &mut Vec<u8>, tag: u8, body: &[u8]) {
+    out.extend_from_slice(&SYNC_MARKER);
+    for _ in 0..SECTION_FIELDS_COPIES {
+        out.push(tag);
+        out.extend_from_slice(&(body.len() as u32).to_le_bytes());
+    }
+    out.extend_from_slice(&CASTAGNOLI.checksum(body).to_le_bytes());
+    out.extend_from_slice(body);
+}
+
+/// The sync marker with no more than one damaged byte
+fn is_sync_marker(bytes: &[u8]) -> bool {
+    bytes.len() >= SYNC_MARKER.len() &&
+        SYNC_MARKER.iter().zip(bytes.iter()).filter(|(a, b)| a != b).count() <= 1
+}
+
+fn next_sync_marker(data: &[u8], from: usize) -> Option<usize> {
+    (from..data.len().saturating_sub(SYNC_MARKER.len() - 1)).find(|i| is_sync_marker(&data[*i..]))
+}
+
+fn majority<T: Eq + Hash + Copy>(values: &[T]) -> Option<T> {
+    let mut frequency: HashMap<T, usize> = HashMap::new();
+    for v in values {
+        *frequency.entry(*v).or_insert(0) += 1;
+    }
+    frequency.into_iter().find(|(_, count)| *count * 2 > values.len()).map(|(v, _)| v)
+}
+
+/// Finds all sections in the damaged stored data.
+///
+/// The sections with the damaged length are cut by the next sync marker, so the reader is
+/// synchronized again and the following sections are still found.
+pub fn read_sections(data: &[u8]) -> Vec<Section<'_>> {
+    let mut sections = Vec::new();
+    let mut from = 0;
+    while let Some(position) = next_sync_marker(data, from) {
+        let fields_from = position + SYNC_MARKER.len();
+        let body_from = position + SECTION_HEADER_LENGTH;
+        if body_from > data.len() {
+            break;
+        }
+        let fields: Vec<(u8, u32)> = data[fields_from..fields_from + SECTION_FIELDS_COPIES * SECTION_FIELDS_LENGTH]
+            .chunks(SECTION_FIELDS_LENGTH)
+            .map(|f| (f[0], u32::from_le_bytes([f[1], f[2], f[3], f[4]])))
+            .collect();
+        let tag = majority(fields.iter().map(|(tag, _)| *tag).collect::<Vec<u8>>().as_slice());
+        let declared_len = majority(fields.iter().map(|(_, len)| *len).collect::<Vec<u32>>().as_slice())
+            .map(|len| len as usize);
+        let len = declared_len.filter(|len| body_from + len <= data.len());
+        let checksum_from = body_from - 4;
+        let checksum = u32::from_le_bytes([data[checksum_from], data[checksum_from + 1], data[checksum_from + 2], data[checksum_from + 3]]);
+
+        let body_until = match len {
+            Some(len) => body_from + len,
+            // resynchronize with the next section
+            None => next_sync_marker(data, body_from).unwrap_or(data.len()),
+        };
+        let body = &data[body_from..min(body_until, data.len())];
+        // the last section is cut if its length is longer than the rest of the data
+        let missing = match (len, declared_len) {
+            (None, Some(declared_len)) if body_until == data.len() => declared_len - body.len(),
+            _ => 0,
+        };
+        if let Some(tag) = tag {
+            sections.push(Section {
+                tag,
+                offset: body_from,
+                body,
+                intact: len.is_some() && CASTAGNOLI.checksum(body) == checksum,
+                missing,
+            });
+        }
+        from = body_until;
+    }
+    sections
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sections_data() -> (Vec<u8>, Vec<Vec<u8>>) {
+        let bodies: Vec<Vec<u8>> = (1..=3u8).map(|i| vec![i; 20 * i as usize]).collect();
+        let mut data = Vec::new();
+        for (tag, body) in bodies.iter().enumerate() {
+            write_section(&mut data, tag as u8, body.as_slice());
+        }
+        (data, bodies)
+    }
+
+    #[test]
+    fn sections_are_read() {
+        let (data, bodies) = sections_data();
+
+        let sections = read_sections(data.as_slice());
+
+        assert_eq!(sections.len(), 3);
+        for (tag, (section, body)) in sections.iter().zip(bodies.iter()).enumerate() {
+            assert_eq!(section.tag, tag as u8);
+            assert_eq!(section.body, body.as_slice());
+            assert!(section.intact);
+        }
+    }
+
+    #[test]
+    fn damaged_length_is_restored_by_the_other_copies() {
+        let (mut data, bodies) = sections_data();
+        // the first length copy of the first section
+        data[SYNC_MARKER.len() + 1] = 0xff;
+
+        let sections = read_sections(data.as_slice());
+
+        assert_eq!(sections.len(), 3);
+        assert_eq!(sections[0].body, bodies[0].as_slice());
+        assert!(sections[0].intact);
+    }
+
+    #[test]
+    fn reader_is_synchronized_after_the_damaged_section() {
+        let (mut data, bodies) = sections_data();
+        // all the length copies of the first section
+        for i in 0..SECTION_FIELDS_COPIES {
+            data[SYNC_MARKER.len() + i * SECTION_FIELDS_LENGTH + 1] = i as u8 + 0x10;
+        }
+
+        let sections = read_sections(data.as_slice());
+
+        assert_eq!(sections.len(), 3);
+        assert_eq!(sections[0].body, bodies[0].as_slice());
+        assert!(!sections[0].intact);
+        assert_eq!(sections[1].body, bodies[1].as_slice());
+        assert!(sections[1].intact);
+        assert_eq!(sections[2].body, bodies[2].as_slice());
+        assert!(sections[2].intact);
+    }
+
+    #[test]
+    fn cut_section_is_found_with_the_missing_length() {
+        let (data, bodies) = sections_data();
+
+        let sections = read_sections(&data[..data.len() - 5]);
+
+        assert_eq!(sections.len(), 3);
+        assert_eq!(sections[2].body, &bodies[2][..bodies[2].len() - 5]);
+        assert_eq!(sections[2].missing, 5);
+        assert!(!sections[2].intact);
+    }
+
+    #[test]
+    fn section_with_destroyed_marker_is_skipped() {
+        let (mut data, bodies) = sections_data();
+        data[0] = 0;
+        data[1] = 0;
+
+        let sections = read_sections(data.as_slice());
+
+        assert_eq!(sections.len(), 2);
+        assert_eq!(sections[0].body, bodies[1].as_slice());
+        assert_eq!(sections[1].body, bodies[2].as_slice());
+    }
+}
@@ -5,6 +5,7 @@ use reed_solomon::Decoder;
 use reed_solomon::DecoderError;
 
 use crate::objects::ECCData;
+use crate::planner::EccPlan;
 
 pub fn copy_n_times(data: &[u8], times: usize) -> Vec<ECCData> {
     let mut result = Vec::with_capacity(times as usize);
@@ -14,23 +15,12 @@ pub fn copy_n_times(data: &[u8], times: usize) -> Vec<ECCData> {
     return result;
 }
 
-pub fn create_ecc(data: &[u8], allowed_data_damage_level: f32) -> Vec<ECCData> {
-    let mut reed_solomon_damage_level = (data.len() as f32 * allowed_data_damage_level) % 1.0;
-    let copy_damage_level = (data.len() as f32 * allowed_data_damage_level) / 1.0;
-    let mut copy_ecc_times = copy_damage_level as usize;
-    if copy_ecc_times > 1 && reed_solomon_damage_level == 0.0 {
-        reed_solomon_damage_level = 1.0;
-        copy_ecc_times -= 1;
-    };
-    let reed_solomon_ecc_len = data.len() * (2.0 * reed_solomon_damage_level) as usize;
+pub fn create_ecc_with_plan(data: &[u8], plan: &EccPlan) -> Vec<ECCData> {
     let mut result: Vec<ECCData> = Vec::new();
-    if reed_solomon_ecc_len > 0 {
-        let ecc_buffer = encode_reed_solomon(data, reed_solomon_ecc_len);
-        result.push(ECCData { ecc_algorithm: 1, ecc: Vec::from(ecc_buffer.ecc()) })
-    }
-    if copy_ecc_times > 0 {
-        result.extend(copy_n_times(data, copy_ecc_times));
+    if plan.reed_solomon_ecc_len > 0 {
+        result.push(ECCData { ecc_algorithm: 1, ecc: Vec::from(encode_reed_solomon(data, plan.reed_solomon_ecc_len).ecc()) });
     }
+    result.extend(copy_n_times(data, plan.copies.saturating_sub(1)));
 
     result.push(ECCData { ecc_algorithm: 0, ecc: Vec::from(data) });
 
@@ -39,10 +29,10 @@ pub fn create_ecc(data: &[u8], allowed_data_damage_level: f32) -> Vec<ECCData> {
 
 // todo warning if data array len will be corrupted, then only 255-ECC_BYTES can be recovered
 // todo move recovery from serialization
-pub fn recover_with_ecc(data: Buffer, ecc_len: usize) -> Result<Buffer, DecoderError> {
+pub fn recover_with_ecc(data: Buffer, ecc_len: usize, erasures: Option<&[u8]>) -> Result<Buffer, DecoderError> {
     // Length of error correction code
     let dec = Decoder::new(ecc_len);
-    return dec.correct(&*data, None);
+    return dec.correct(&*data, erasures);
 }
 
 pub fn encode_reed_solomon(data: &[u8], ecc_len: usize) -> Buffer {
@@ -51,10 +41,31 @@ pub fn encode_reed_solomon(data: &[u8], ecc_len: usize) -> Buffer {
     return enc.encode(&data[..]);
 }
 
-pub fn recover_reed_solomon(data: Buffer, ecc_len: usize) -> Result<Buffer, DecoderError> {
+/// Corrects the Reed-Solomon codeword `data`.
+///
+/// `erasures` are the known damaged positions in the codeword. An erasure costs one ECC byte
+/// instead of the two needed for an error at an unknown position, so with `ecc_len` ECC bytes
+/// `2 * errors + erasures.len() <= ecc_len` can be corrected.
+pub fn recover_reed_solomon(data: Buffer, ecc_len: usize, erasures: Option<&[u8]>) -> Result<Buffer, DecoderError> {
     // Length of error correction code
     let dec = Decoder::new(ecc_len);
-    return dec.correct(&*data, None);
+    return dec.correct(&*data, erasures);
+}
+
+/// Converts the damaged positions to the Reed-Solomon erasures format.
+/// Returns `None` if there're no erasures or they can't be corrected anyway.
+pub fn reed_solomon_erasures(positions: &[usize], codeword_len: usize, ecc_len: usize) -> Option<Vec<u8>> {
+    let mut erasures: Vec<u8> = positions.iter()
+        .filter(|p| **p < codeword_len && **p <= u8::MAX as usize)
+        .map(|p| *p as u8)
+        .collect();
+    erasures.sort();
+    erasures.dedup();
+    if erasures.is_empty() || erasures.len() > ecc_len {
+        None
+    } else {
+        Some(erasures)
+    }
 }
 
 #[cfg(test)]
@@ -84,7 +95,7 @@ mod tests {
             }
 
             // Try to recover data
-            let recovered = recover_reed_solomon(corrupted, ecc_len).unwrap();
+            let recovered = recover_reed_solomon(corrupted, ecc_len, None).unwrap();
 
             assert_eq!(data, recovered.data());
         }
@@ -111,7 +122,7 @@ mod tests {
             }
 
             // Try to recover data
-            let recovered = recover_reed_solomon(corrupted, ecc_len).unwrap();
+            let recovered = recover_reed_solomon(corrupted, ecc_len, None).unwrap();
 
             assert_eq!(data, recovered.data());
         }
@@ -139,7 +150,34 @@ mod tests {
                 corrupted[*i] = 0x0;
             }
             // Try to recover data
-            let recovered = recover_reed_solomon(corrupted, ecc_len).unwrap();
+            let recovered = recover_reed_solomon(corrupted, ecc_len, None).unwrap();
+
+            assert_eq!(data, recovered.data());
+        }
+    }
+
+    #[test]
+    fn ecc_corrects_twice_more_erasures_than_errors() {
+        let data = "1234567890".as_bytes();
+        let ecc_len = data.len();
+
+        // Encode data
+        let encoded = encode_reed_solomon(data, ecc_len);
+
+        for _ in 0..100 {
+            let mut rng = thread_rng();
+            let sl: Vec<_> = (0..encoded.len()).collect();
+
+            // Damage as many bytes as ECC length, it's twice more than errors can be corrected
+            let mut corrupted = encoded.clone();
+            let damaged: Vec<usize> = sl.choose_multiple(&mut rng, ecc_len).cloned().collect();
+            for i in damaged.iter() {
+                corrupted[*i] = 0x0;
+            }
+
+            // Try to recover data with the known damaged positions
+            let erasures = reed_solomon_erasures(damaged.as_slice(), encoded.len(), ecc_len);
+            let recovered = recover_reed_solomon(corrupted, ecc_len, erasures.as_ref().map(|e| e.as_slice())).unwrap();
 
             assert_eq!(data, recovered.data());
         }
@@ -0,0 +1,372 @@
+use std::rc::Rc;
+use std::str::FromStr;
+use std::time::{Duration, Instant};
+
+use crate::error::*;
+use crate::paper::{decode_lines, encode_lines};
+
+/// The character to mark the unreadable characters in the text representation
+pub const UNKNOWN_CHARACTER: char = '?';
+
+/// Decodes base58 string with the unreadable characters marked by `UNKNOWN_CHARACTER`.
+///
+/// Returns the decoded bytes and the positions of the bytes which can be affected by the unknown
+/// characters. These positions can be passed as erasures to the ECC.
+pub fn decode_base58_with_erasures(text: &str) -> Result<(Vec<u8>, Vec<usize>)> {
+    let chars: Vec<char> = text.chars().collect();
+    let leading_zeros = chars.iter().take_while(|c| **c == '1').count();
+    // the unknown character is replaced with the zero digit, but not in the leading position,
+    // otherwise it will change the decoded data length
+    let replaced: String = chars.iter().enumerate().map(|(i, c)| {
+        if *c != UNKNOWN_CHARACTER {
+            *c
+        } else if i == leading_zeros {
+            '2'
+        } else {
+            '1'
+        }
+    }).collect();
+    let decoded = bs58::decode(replaced).into_vec()
+        .map_err(|e| Box::new(ErrorKind::Base58DecodingError(e)))?;
+
+    let mut erasures: Vec<usize> = chars.iter().enumerate()
+        .filter(|(_, c)| **c == UNKNOWN_CHARACTER)
+        .flat_map(|(i, _)| affected_bytes(chars.len() - 1 - i, decoded.as_slice()))
+        .collect();
+    erasures.sort();
+    erasures.dedup();
+    Ok((decoded, erasures))
+}
+
+/// Returns the bytes affected by the base58 digit with weight `58^power` in the `decoded` data.
+///
+/// The digit change is multiplied by `58^power = 2^power * 29^power`, so the lower `power` bits stay
+/// the same and the change can reach the bytes above the digit weight with the carry.
+/// The unknown digit is replaced with the lowest one, so the real value is only greater and
+/// the carry goes through every `0xff` byte above the digit weight.
+/// Note that a base58 character affects many bytes, so the erasures are the most useful with the
+/// strong ECC or at the end of the text.
+fn affected_bytes(power: usize, decoded: &[u8]) -> Vec<usize> {
+    let len = decoded.len();
+    let lowest_byte = power / 8;
+    let mut highest_byte = ((power + 1) as f64 * 58f64.log(256.0)).ceil() as usize + 1;
+    while highest_byte < len && decoded[len - 1 - highest_byte] == 0xff {
+        highest_byte += 1;
+    }
+    (lowest_byte..=highest_byte)
+        .filter(|b| *b < len)
+        .map(|b| len - 1 - b)
+        .collect()
+}
+
+/// The base58 alphabet of Bitcoin used by `bs58` by default
+pub const BASE58_CHARACTERS: &str = "123456789ABCDEFGHJKLMNPQRSTUVWXYZabcdefghijkmnopqrstuvwxyz";
+/// The groups of the characters which are easily confused in handwriting.
+/// The characters out of the base58 alphabet are replaced with the other characters of their group.
+const CONFUSABLE_CHARACTERS: &[&str] = &[
+    "1iIlLj7", "o0OaQ", "5Ss", "2Zz", "8B3", "6bG", "9gq", "Cc", "Kk", "Mm", "Pp", "UuVv", "Ww", "Xx", "Yy", "4A", "hn", "rv", "Ee", "Dd",
+];
+
+/// The single typo in the text
+#[derive(Debug, Copy, Clone, PartialEq)]
+enum Typo {
+    Substitution(usize, char),
+    Transposition(usize),
+    Deletion(usize),
+    Insertion(usize, char),
+}
+
+/// The base58 characters which can be mistyped as `c`
+fn confusable_with(c: char) -> Vec<char> {
+    CONFUSABLE_CHARACTERS.iter()
+        .filter(|group| group.contains(c))
+        .flat_map(|group| group.chars())
+        .filter(|other| *other != c && BASE58_CHARACTERS.contains(*other))
+        .collect()
+}
+
+/// Fixes the typos in the text, the typos are in the increasing positions and don't change each other positions
+fn fix_typos(chars: &[char], typos: &[Typo]) -> String {
+    let mut fixed = chars.to_vec();
+    for typo in typos.iter().rev() {
+        match *typo {
+            Typo::Substitution(i, c) => fixed[i] = c,
+            Typo::Transposition(i) => fixed.swap(i, i + 1),
+            Typo::Deletion(i) => {
+                fixed.remove(i);
+            }
+            Typo::Insertion(i, c) => fixed.insert(i, c),
+        }
+    }
+    fixed.into_iter().collect()
+}
+
+/// The single typos at the position `i`, the most likely are first
+fn typos_at(chars: &[char], i: usize) -> Vec<Typo> {
+    let known = chars[i] != UNKNOWN_CHARACTER;
+    let mut typos: Vec<Typo> = Vec::new();
+    if known {
+        typos.extend(confusable_with(chars[i]).into_iter().map(|c| Typo::Substitution(i, c)));
+    }
+    if i + 1 < chars.len() && chars[i] != chars[i + 1] {
+        typos.push(Typo::Transposition(i));
+    }
+    typos.push(Typo::Deletion(i));
+    if known {
+        typos.extend(BASE58_CHARACTERS.chars().filter(|c| *c != chars[i]).map(|c| Typo::Substitution(i, c)));
+    }
+    typos.extend(BASE58_CHARACTERS.chars().map(|c| Typo::Insertion(i, c)));
+    typos
+}
+
+/// The positions of the characters out of the base58 alphabet, the unknown characters aren't counted
+pub fn invalid_characters(text: &str) -> Vec<usize> {
+    text.chars().enumerate()
+        .filter(|(_, c)| *c != UNKNOWN_CHARACTER && !BASE58_CHARACTERS.contains(*c))
+        .map(|(i, _)| i)
+        .collect()
+}
+
+/// The texts with a single typo fixed next to the `damaged` character positions
+pub fn typo_candidates_near(text: &str, damaged: &[usize]) -> impl Iterator<Item=String> {
+    let chars: Rc<Vec<char>> = Rc::new(text.chars().collect());
+    let mut positions: Vec<usize> = damaged.iter()
+        .flat_map(|p| p.saturating_sub(1)..=p + 1)
+        .filter(|p| *p < chars.len())
+        .collect();
+    positions.sort();
+    positions.dedup();
+    positions.into_iter()
+        .flat_map(move |i| {
+            let chars = chars.clone();
+            typos_at(chars.as_slice(), i).into_iter().map(move |typo| fix_typos(chars.as_slice(), &[typo]))
+        })
+}
+
+/// The texts with the typos fixed, the most likely typos are first.
+///
+/// The single confusable characters and the transpositions are tried first, then the deleted,
+/// the other mistaken and the inserted characters, and the pairs of confusable characters at last.
+/// The unknown characters are kept for the ECC.
+pub fn typo_candidates(text: &str) -> impl Iterator<Item=String> {
+    let chars: Rc<Vec<char>> = Rc::new(text.chars().collect());
+    let len = chars.len();
+    let known = {
+        let chars = chars.clone();
+        move |i: usize| chars[i] != UNKNOWN_CHARACTER
+    };
+    let confusable = {
+        let chars = chars.clone();
+        let known = known.clone();
+        (0..len).filter(move |i| known(*i))
+            .flat_map(move |i| confusable_with(chars[i]).into_iter().map(move |c| vec![Typo::Substitution(i, c)]))
+    };
+    let transpositions = {
+        let chars = chars.clone();
+        (0..len.saturating_sub(1)).filter(move |i| chars[*i] != chars[*i + 1]).map(|i| vec![Typo::Transposition(i)])
+    };
+    let deletions = (0..len).map(|i| vec![Typo::Deletion(i)]);
+    let substitutions = {
+        let chars = chars.clone();
+        let known = known.clone();
+        (0..len).filter(move |i| known(*i))
+            .flat_map(move |i| {
+                let original = chars[i];
+                BASE58_CHARACTERS.chars().filter(move |c| *c != original).map(move |c| vec![Typo::Substitution(i, c)])
+            })
+    };
+    let insertions = (0..=len).flat_map(|i| BASE58_CHARACTERS.chars().map(move |c| vec![Typo::Insertion(i, c)]));
+    let confusable_pairs = {
+        let chars = chars.clone();
+        (0..len).filter(move |i| known(*i)).flat_map(move |i| {
+            let chars = chars.clone();
+            (i + 1..len).flat_map(move |j| {
+                let second = confusable_with(chars[j]);
+                confusable_with(chars[i]).into_iter()
+                    .flat_map(move |a| second.clone().into_iter().map(move |b| vec![Typo::Substitution(i, a), Typo::Substitution(j, b)]))
+            })
+        })
+    };
+    confusable
+        .chain(transpositions)
+        .chain(deletions)
+        .chain(substitutions)
+        .chain(insertions)
+        .chain(confusable_pairs)
+        .map(move |typos| fix_typos(chars.as_slice(), typos.as_slice()))
+}
+
+/// The bounds of the typo repair, every candidate is read as the stored data, so the long texts
+/// can't be searched through
+#[derive(Debug, Copy, Clone)]
+pub struct RepairLimits {
+    /// Number of the candidates to check
+    pub candidates: usize,
+    pub time: Duration,
+}
+
+impl Default for RepairLimits {
+    fn default() -> Self {
+        RepairLimits { candidates: 200_000, time: Duration::from_secs(60) }
+    }
+}
+
+/// Finds the most likely text without the typos which is accepted by `is_valid`,
+/// e.g. the decoded stored data has the valid checksums.
+///
+/// The single typos next to the characters out of the base58 alphabet are tried first.
+/// Returns `None` if no candidate is valid within `limits`.
+pub fn repair_typos<F: FnMut(&str) -> bool>(text: &str, limits: &RepairLimits, mut is_valid: F) -> Option<String> {
+    let start = Instant::now();
+    typo_candidates_near(text, invalid_characters(text).as_slice())
+        .chain(typo_candidates(text))
+        .take(limits.candidates)
+        .take_while(|_| start.elapsed() < limits.time)
+        .find(|candidate| is_valid(candidate.as_str()))
+}
+
+/// The text representations of the stored data
+#[derive(Eq, PartialEq, Debug, Copy, Clone)]
+pub enum TextEncoding {
+    Base58,
+    /// The numbered lines with checksums, see `paper`
+    Paper,
+}
+
+impl TextEncoding {
+    pub fn encode(&self, bytes: &[u8]) -> String {
+        match self {
+            TextEncoding::Base58 => bs58::encode(bytes).into_string(),
+            TextEncoding::Paper => encode_lines(bytes),
+        }
+    }
+
+    /// Decodes the text, also returns the positions of the bytes which are unreadable or lost
+    pub fn decode_with_erasures(&self, text: &str) -> Result<(Vec<u8>, Vec<usize>)> {
+        match self {
+            TextEncoding::Base58 => decode_base58_with_erasures(text.trim()),
+            TextEncoding::Paper => decode_lines(text).map(|transcription| (transcription.data, transcription.erasures)),
+        }
+    }
+}
+
+impl FromStr for TextEncoding {
+    type Err = String;
+
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "base58" => Ok(TextEncoding::Base58),
+            "paper" => Ok(TextEncoding::Paper),
+            _ => Err(format!("Unknown text encoding '{}', use base58 or paper", s)),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::planner::EccPlan;
+    use crate::serialization::{add_ecc_and_crc_with_plan, try_to_read_stored_data_with_erasures};
+
+    use super::*;
+
+    #[test]
+    fn unknown_characters_are_mapped_to_the_damaged_bytes() {
+        let data: Vec<u8> = (0..64).map(|i| (i * 37 + 11) as u8).collect();
+        let encoded = bs58::encode(&data).into_string();
+        for i in 0..encoded.len() {
+            let mut damaged: Vec<char> = encoded.chars().collect();
+            damaged[i] = UNKNOWN_CHARACTER;
+            let damaged: String = damaged.into_iter().collect();
+
+            let (decoded, erasures) = decode_base58_with_erasures(damaged.as_str()).unwrap();
+
+            assert_eq!(decoded.len(), data.len());
+            for (position, (a, b)) in decoded.iter().zip(data.iter()).enumerate() {
+                if a != b {
+                    assert!(erasures.contains(&position));
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn unknown_characters_are_mapped_through_the_carries() {
+        // the replaced digit borrows through the zero bytes, the decoded ones become 0xff
+        for last in 0..=255u8 {
+            let data = vec![2, 0, 0, 0, 0, 0, 0, 0, 0, last];
+            let encoded = bs58::encode(&data).into_string();
+            let damaged = format!("{}{}", &encoded[..encoded.len() - 1], UNKNOWN_CHARACTER);
+
+            let (decoded, erasures) = decode_base58_with_erasures(damaged.as_str()).unwrap();
+
+            for (position, (a, b)) in decoded.iter().zip(data.iter()).enumerate() {
+                if a != b {
+                    assert!(erasures.binary_search(&position).is_ok(), "{} isn't erased in {:?}", position, decoded);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn every_kind_of_typo_is_fixed() {
+        let text = "3pNwa6r5";
+        let candidates: Vec<String> = typo_candidates(text).collect();
+
+        for typo in ["3pNwa6rS", "3pwNa6r5", "3pNwa65", "3pNwaXr5", "3pNwa6rr5", "3pNWa6rS"].iter() {
+            assert!(typo_candidates(typo).any(|c| c == text), "{} isn't fixed", typo);
+        }
+        assert!(!candidates.contains(&text.to_string()));
+    }
+
+    #[test]
+    fn mistyped_stored_data_is_repaired() {
+        let data = "1234567890".as_bytes();
+        let plan = EccPlan::new(data.len(), 0, 1);
+        let encoded = add_ecc_and_crc_with_plan(data.to_vec(), &plan).unwrap();
+        let text = bs58::encode(&encoded).into_string();
+        // the repaired text should be the same stored data, not only readable
+        let is_valid = |candidate: &str| decode_base58_with_erasures(candidate)
+            .and_then(|(bytes, erasures)| try_to_read_stored_data_with_erasures(bytes.as_slice(), erasures.as_slice())
+                .map(|recovered| add_ecc_and_crc_with_plan(recovered, &plan).map(|stored| stored == bytes).unwrap_or(false)))
+            .unwrap_or(false);
+        // the swapped characters which break the stored data
+        let mistyped = (0..text.len() - 1).rev().map(|i| {
+            let mut chars: Vec<char> = text.chars().collect();
+            chars.swap(i, i + 1);
+            chars.into_iter().collect::<String>()
+        }).find(|mistyped| !is_valid(mistyped.as_str())).unwrap();
+
+        assert_eq!(repair_typos(mistyped.as_str(), &RepairLimits::default(), is_valid), Some(text));
+    }
+
+    #[test]
+    fn typos_next_to_invalid_characters_are_tried_first() {
+        let text = "3pNwa6r5".repeat(20);
+        let mut mistyped: Vec<char> = text.chars().collect();
+        mistyped[150] = '0';
+        let mistyped: String = mistyped.into_iter().collect();
+        let mut checked = 0;
+
+        let repaired = repair_typos(mistyped.as_str(), &RepairLimits::default(), |candidate| {
+            checked += 1;
+            candidate == text
+        });
+
+        assert_eq!(repaired, Some(text));
+        assert!(checked < 200, "{} candidates are checked", checked);
+    }
+
+    #[test]
+    fn repair_stops_at_the_candidates_limit() {
+        let limits = RepairLimits { candidates: 10, ..RepairLimits::default() };
+        let mut checked = 0;
+
+        let repaired = repair_typos("3pNwa6r5", &limits, |_| {
+            checked += 1;
+            false
+        });
+
+        assert_eq!(repaired, None);
+        assert_eq!(checked, 10);
+    }
+}
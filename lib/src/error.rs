@@ -4,6 +4,8 @@ use std::io;
 
 use shamirsecretsharing::SSSError;
 
+use crate::report::RecoveryReport;
+
 /// The result of a serialization or deserialization operation.
 pub type Result<T> = ::std::result::Result<T, Error>;
 
@@ -23,10 +25,20 @@ pub enum ErrorKind {
     AEADDecryptionError(chacha20_poly1305_aead::DecryptError),
     ShamirsSecretSharingEncryptionError(SSSError),
     ShamirsSecretSharingDecryptionError(SSSError),
+    /// The text representation of the stored data can't be decoded.
+    Base58DecodingError(bs58::decode::Error),
     /// If (de)serializing a message takes more than the provided size limit, this
     /// error is returned.
     SizeLimit,
     EmptyData,
+    /// There's no ECC plan which survives the requested damage.
+    UnreachableDamageTarget,
+    /// The stored data is written by a newer version.
+    UnsupportedFormatVersion(u64),
+    /// The share with the index doesn't belong to the encrypted box.
+    ShareFromAnotherSet(u8),
+    /// The data can't be restored with all the ECC found, the report shows what's left.
+    UnrecoverableData(RecoveryReport),
 }
 
 impl StdError for ErrorKind {
@@ -38,9 +50,14 @@ impl StdError for ErrorKind {
             ErrorKind::StoredDataSerializationError(_) => error::Error::description(self),
             ErrorKind::AEADEncryptionError(_) => error::Error::description(self),
             ErrorKind::AEADDecryptionError(_) => error::Error::description(self),
-            ErrorKind::EmptyData => error::Error::description(self),
+            ErrorKind::EmptyData => "the data is empty",
+            ErrorKind::UnreachableDamageTarget => "the damage target can't be reached",
+            ErrorKind::UnsupportedFormatVersion(_) => "the format version isn't supported",
+            ErrorKind::ShareFromAnotherSet(_) => "the share belongs to another encrypted box",
+            ErrorKind::UnrecoverableData(_) => "the data can't be restored",
             ErrorKind::ShamirsSecretSharingEncryptionError(_) => error::Error::description(self),
             ErrorKind::ShamirsSecretSharingDecryptionError(_) => error::Error::description(self),
+            ErrorKind::Base58DecodingError(_) => error::Error::description(self),
             ErrorKind::SizeLimit => "the size limit has been reached",
         }
     }
@@ -52,11 +69,16 @@ impl StdError for ErrorKind {
             ErrorKind::AEADDecryptionError(ref err) => Some(err),
             ErrorKind::ShamirsSecretSharingEncryptionError(ref err) => Some(err),
             ErrorKind::ShamirsSecretSharingDecryptionError(ref err) => Some(err),
+            ErrorKind::Base58DecodingError(ref err) => Some(err),
             ErrorKind::StoredDataDeserializationError(ref err) => None,
             ErrorKind::StoredDataSerializationError(ref err) => Some(err),
             ErrorKind::ECCRecoveryError => None,
             ErrorKind::SizeLimit => None,
             ErrorKind::EmptyData => None,
+            ErrorKind::UnreachableDamageTarget => None,
+            ErrorKind::UnsupportedFormatVersion(_) => None,
+            ErrorKind::ShareFromAnotherSet(_) => None,
+            ErrorKind::UnrecoverableData(_) => None,
         }
     }
 }
@@ -76,10 +98,15 @@ impl fmt::Display for ErrorKind {
             ErrorKind::StoredDataSerializationError(ref err) => write!(fmt, "Stored data serialization error: {}", err),
             ErrorKind::SizeLimit => write!(fmt, "{}", self.description()),
             ErrorKind::EmptyData => write!(fmt, "{}", self.description()),
+            ErrorKind::UnreachableDamageTarget => write!(fmt, "{}", self.description()),
+            ErrorKind::UnsupportedFormatVersion(version) => write!(fmt, "The format version {} isn't supported, please update dataragon", version),
+            ErrorKind::ShareFromAnotherSet(index) => write!(fmt, "The share {} belongs to another encrypted box", index),
+            ErrorKind::UnrecoverableData(ref report) => write!(fmt, "Restoring data error from error-correcting code. What's found:\n{}", report),
             ErrorKind::AEADEncryptionError(ref err) => write!(fmt, "AEAD encryption error: {}", err),
             ErrorKind::AEADDecryptionError(ref err) => write!(fmt, "AEAD decryption error: {}", err),
             ErrorKind::ShamirsSecretSharingEncryptionError(ref err) => write!(fmt, "Shamir's Secret Sharing encryption error: {}", err),
             ErrorKind::ShamirsSecretSharingDecryptionError(ref err) => write!(fmt, "Shamir's Secret Sharing decryption error: {}", err),
+            ErrorKind::Base58DecodingError(ref err) => write!(fmt, "Base58 decoding error: {}", err),
         }
     }
 }
\ No newline at end of file
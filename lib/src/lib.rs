@@ -1,39 +1,212 @@
 extern crate map_in_place;
 
-use map_in_place::MapVecInPlace;
+use blake2::Blake2bMac512;
+use blake2::digest::{KeyInit, Mac};
+use shamirsecretsharing::hazmat::KEYSHARE_SIZE;
+use zeroize::{Zeroize, Zeroizing};
 
-use crate::error::Result;
-use crate::objects::CryptoSecretbox;
-use crate::serialization::{add_ecc_and_crc, try_to_read_stored_data};
-use crate::shamir::{combine_data_shares, create_data_shares};
+use crate::error::{ErrorKind, Result};
+use crate::objects::{CryptoSecretbox, SET_ID_LENGTH, Share};
+use crate::planner::EccPlan;
+use crate::encoding::TextEncoding;
+use crate::report::RecoveryReport;
+use crate::serialization::{add_ecc_and_crc_with_plan, to_bytes, try_to_read_stored_data_with_erasures, try_to_read_stored_data_with_report};
+use crate::shamir::{combine_data_shares, create_data_shares, inspect_data_shares};
 
+mod container;
+mod scan;
+mod parallel;
 mod ecc;
 mod shamir;
 pub mod objects;
 pub mod serialization;
 pub mod error;
+pub mod encoding;
+pub mod planner;
+pub mod checksum;
+pub mod report;
+pub mod paper;
+pub mod simulator;
+#[cfg(test)]
+mod test_vectors;
+
+/// Reed-Solomon ECC length of a share.
+/// The shares are short and often typed by hand, so their ECC is lighter than the encrypted box one.
+const SHARE_REED_SOLOMON_ECC_LEN: usize = 16;
+/// The share header isn't protected by Reed-Solomon ECC to keep the share short
+const SHARE_HEADER_COPIES: usize = 3;
+pub const FINGERPRINT_LENGTH: usize = 16;
+pub const RECORD_TAG_LENGTH: usize = 32;
+const RECORD_KEY_LENGTH: usize = 32;
+
+/// The outcome of a recovery drill, the recovered key and the secret are zeroized before it's returned
+pub struct Drill {
+    /// Indices of the combined shares
+    pub indices: Vec<u8>,
+    /// The secret fingerprint keyed by the recovered key, so the secret can't be guessed by it without the shares
+    pub fingerprint: [u8; FINGERPRINT_LENGTH],
+    /// Derived from the recovered key to authenticate the drill records, the secret can't be recovered with it
+    record_key: [u8; RECORD_KEY_LENGTH],
+}
+
+impl Drill {
+    /// The tag of the drill record, the next drill with the shares of the same set makes the same tag
+    pub fn authenticate(&self, record: &[u8]) -> [u8; RECORD_TAG_LENGTH] {
+        let mut tag = [0; RECORD_TAG_LENGTH];
+        tag.copy_from_slice(&keyed_hash(b"dataragon drill record", &self.record_key, record)[..RECORD_TAG_LENGTH]);
+        tag
+    }
+}
+
+impl Drop for Drill {
+    fn drop(&mut self) {
+        self.record_key.zeroize();
+    }
+}
+
+/// Keyed BLAKE2b-512 of the domain and the data, the domain is prefixed by its length so it can't run into the data
+fn keyed_hash(domain: &[u8], key: &[u8], data: &[u8]) -> Vec<u8> {
+    let mut mac = <Blake2bMac512 as KeyInit>::new_from_slice(key).expect("the keys are 32 bytes long");
+    mac.update(&[domain.len() as u8]);
+    mac.update(domain);
+    mac.update(data);
+    mac.finalize().into_bytes().to_vec()
+}
+
+/// The secret fingerprint keyed by the key of the encrypted box
+fn fingerprint(key: &[u8], text: &[u8]) -> [u8; FINGERPRINT_LENGTH] {
+    let mut fingerprint = [0; FINGERPRINT_LENGTH];
+    fingerprint.copy_from_slice(&keyed_hash(b"dataragon secret fingerprint", key, text)[..FINGERPRINT_LENGTH]);
+    fingerprint
+}
 
 pub fn split(text: &[u8], allowed_data_damage_level: f32, count: u8, threshold: u8) -> Result<(Vec<Vec<u8>>, CryptoSecretbox)> {
-    return create_data_shares(&text[..], count, threshold)
-//        .map(|(shares, secret_box)| {
-//        let shares_with_crc_and_ecc = shares.filter_map(|share| add_ecc_and_crc(share, allowed_data_damage_level).ok());
-//        return (shares_with_crc_and_ecc, secret_box);
-//    });
+    split_with_fingerprint(text, count, threshold).map(|(shares, secret_box, _)| (shares, secret_box))
+}
+
+/// Splits the secret and makes its fingerprint with the key before it's zeroized, it's the same as the drill one
+pub fn split_with_fingerprint(text: &[u8], count: u8, threshold: u8) -> Result<(Vec<Vec<u8>>, CryptoSecretbox, [u8; FINGERPRINT_LENGTH])> {
+    let (keyshares, secret_box, fingerprint) = create_data_shares(text, count, threshold, |key| fingerprint(key, text))?;
+    let set_id = secret_box.set_id();
+    keyshares.iter()
+        .map(|keyshare| encode_share(&Zeroizing::new(Share { set_id, threshold, count, keyshare: keyshare.clone() })))
+        .collect::<Result<Vec<Vec<u8>>>>()
+        .map(|shares| (shares, secret_box, fingerprint))
+}
+
+/// The key shares of the encrypted box, the shares of another set are rejected.
+/// They're zeroized when they're dropped.
+fn keyshares_of(shares: &[Share], secret_box: &CryptoSecretbox) -> Result<Zeroizing<Vec<Vec<u8>>>> {
+    let set_id = secret_box.set_id();
+    shares.iter()
+        .map(|share| {
+            if share.is_legacy() || share.set_id == set_id {
+                Ok(share.keyshare.clone())
+            } else {
+                Err(ErrorKind::ShareFromAnotherSet(share.index()).into())
+            }
+        })
+        .collect::<Result<Vec<Vec<u8>>>>()
+        .map(Zeroizing::new)
+}
+
+fn decode_shares(shares: &[Vec<u8>]) -> Result<Zeroizing<Vec<Share>>> {
+    shares.iter().map(|s| decode_share(s.as_slice())).collect::<Result<Vec<Share>>>().map(Zeroizing::new)
 }
 
 pub fn combine(shares: Vec<Vec<u8>>, secret_box: &CryptoSecretbox) -> Result<Vec<u8>> {
-//    let successfully_combined_shares: Vec<Vec<u8>> =
-//        shares.filter_map(|s| try_to_read_stored_data(s.as_slice()).ok()).into_iter()
-//        .collect();
+    decode_shares(shares.as_slice()).and_then(|shares| combine_shares(shares.as_slice(), secret_box))
+}
+
+/// Combines the decoded shares, e.g. read by `decode_share_with_erasures`
+pub fn combine_shares(shares: &[Share], secret_box: &CryptoSecretbox) -> Result<Vec<u8>> {
+    let keyshares = keyshares_of(shares, secret_box)?;
+
+    return combine_data_shares(keyshares.as_slice(), secret_box);
+}
+
+/// Recovers the secret and checks it by the AEAD tag of the box without returning it.
+/// Only the share indices, the secret fingerprint and the key to authenticate the drill record are left.
+pub fn drill(shares: Vec<Vec<u8>>, secret_box: &CryptoSecretbox) -> Result<Drill> {
+    decode_shares(shares.as_slice()).and_then(|shares| drill_shares(shares.as_slice(), secret_box))
+}
+
+/// The recovery drill with the decoded shares
+pub fn drill_shares(shares: &[Share], secret_box: &CryptoSecretbox) -> Result<Drill> {
+    let keyshares = keyshares_of(shares, secret_box)?;
+    let mut indices: Vec<u8> = keyshares.iter().filter_map(|keyshare| keyshare.first().cloned()).collect();
+    indices.sort_unstable();
+    indices.dedup();
+
+    return inspect_data_shares(keyshares.as_slice(), secret_box, |key, text| {
+        let fingerprint = fingerprint(key, text);
+        let mut record_key = [0; RECORD_KEY_LENGTH];
+        record_key.copy_from_slice(&keyed_hash(b"dataragon drill record key", key, &[])[..RECORD_KEY_LENGTH]);
+        Drill { indices, fingerprint, record_key }
+    });
+}
+
+/// Stores the share with ECC in the latest format version
+pub fn encode_share(share: &Share) -> Result<Vec<u8>> {
+    to_bytes(share).and_then(|bytes| {
+        let plan = EccPlan { data_len: bytes.len(), reed_solomon_ecc_len: SHARE_REED_SOLOMON_ECC_LEN, copies: 1, header_copies: SHARE_HEADER_COPIES, header_reed_solomon_ecc_len: 0 };
+        add_ecc_and_crc_with_plan(bytes, &plan)
+    })
+}
 
-    return combine_data_shares(shares, secret_box);
+/// Reads the share of any format version.
+/// The version 0 share is a raw key share, it's returned without the set id, threshold and count.
+pub fn decode_share(data: &[u8]) -> Result<Share> {
+    decode_share_with_erasures(data, &[])
 }
 
+/// Reads the share, `erasures` are the known damaged positions in `data`, e.g. the unknown characters.
+/// The version 0 share has no ECC, so it can't have erasures.
+pub fn decode_share_with_erasures(data: &[u8], erasures: &[usize]) -> Result<Share> {
+    try_to_read_stored_data_with_erasures(data, erasures)
+        .and_then(|bytes| postcard::from_bytes::<Share>(bytes.as_slice())
+            .map_err(|e| ErrorKind::StoredDataDeserializationError(Some(e)).into()))
+        .or_else(|e| if data.len() == KEYSHARE_SIZE && erasures.is_empty() {
+            Ok(Share { set_id: [0; SET_ID_LENGTH], threshold: 0, count: 0, keyshare: data.to_vec() })
+        } else {
+            Err(e)
+        })
+}
+
+/// Stores the share of any format version in the latest one.
+/// The legacy share gets the set id of `secret_box`, `threshold` and `count`.
+pub fn migrate_share(data: &[u8], secret_box: &CryptoSecretbox, threshold: u8, count: u8) -> Result<Vec<u8>> {
+    migrate_share_with_erasures(data, &[], secret_box, threshold, count)
+}
+
+/// Migrates the share with the known damaged positions, e.g. the unknown characters
+pub fn migrate_share_with_erasures(data: &[u8], erasures: &[usize], secret_box: &CryptoSecretbox, threshold: u8, count: u8) -> Result<Vec<u8>> {
+    decode_share_with_erasures(data, erasures).map(Zeroizing::new).and_then(|share| {
+        if share.is_legacy() {
+            encode_share(&Zeroizing::new(Share { set_id: secret_box.set_id(), threshold, count, keyshare: share.keyshare.clone() }))
+        } else {
+            encode_share(&share)
+        }
+    })
+}
+
+/// Wraps any bytes in the stored data with ECC and writes it as text, without the secret sharing
+pub fn armor(data: &[u8], plan: &EccPlan, encoding: TextEncoding) -> Result<String> {
+    add_ecc_and_crc_with_plan(data.to_vec(), plan).map(|stored_data| encoding.encode(stored_data.as_slice()))
+}
+
+/// Reads the bytes wrapped by `armor`, the report shows how they were recovered
+pub fn unarmor(text: &str, encoding: TextEncoding) -> Result<(Vec<u8>, RecoveryReport)> {
+    encoding.decode_with_erasures(text)
+        .and_then(|(stored_data, erasures)| try_to_read_stored_data_with_report(stored_data.as_slice(), erasures.as_slice()))
+}
 
 #[cfg(test)]
 mod tests {
     use itertools::Itertools;
 
+    use crate::test_vectors::from_hex;
+
     use super::*;
 
     #[test]
@@ -55,4 +228,104 @@ mod tests {
             }
         }
     }
-}
\ No newline at end of file
+
+    fn golden_share() -> Share {
+        Share { set_id: [1, 2, 3, 4, 5, 6, 7, 8], threshold: 2, count: 3, keyshare: (1..=33).collect() }
+    }
+
+    #[test]
+    fn share_golden_vectors_of_all_versions_are_read() {
+        let legacy = decode_share(from_hex(include_str!("../test-vectors/share-v0.hex")).as_slice()).unwrap();
+        assert!(legacy.is_legacy());
+        assert_eq!(legacy.keyshare, golden_share().keyshare);
+
+        let share = decode_share(from_hex(include_str!("../test-vectors/share-v1.hex")).as_slice()).unwrap();
+        assert_eq!(share, golden_share());
+    }
+
+    #[test]
+    fn legacy_share_is_migrated_with_the_set_information() {
+        let (_, secretbox) = split("supersecret".as_bytes(), 1.0, 3, 2).unwrap();
+        let legacy = from_hex(include_str!("../test-vectors/share-v0.hex"));
+
+        let migrated = decode_share(migrate_share(legacy.as_slice(), &secretbox, 2, 3).unwrap().as_slice()).unwrap();
+
+        assert_eq!(migrated, Share { set_id: secretbox.set_id(), ..golden_share() });
+    }
+
+    #[test]
+    fn share_from_another_set_is_rejected() {
+        let (shares, _) = split("supersecret".as_bytes(), 1.0, 3, 2).unwrap();
+        let (_, other_secretbox) = split("supersecret".as_bytes(), 1.0, 3, 2).unwrap();
+
+        assert!(combine(shares, &other_secretbox).is_err());
+    }
+
+    #[test]
+    fn share_with_unknown_characters_is_restored() {
+        let (shares, secretbox) = split("supersecret".as_bytes(), 1.0, 3, 2).unwrap();
+        let damaged: Vec<Share> = shares.iter().map(|share| {
+            let mut text: Vec<char> = bs58::encode(share).into_string().chars().collect();
+            let len = text.len();
+            text[len - 3..].iter_mut().for_each(|c| *c = encoding::UNKNOWN_CHARACTER);
+            let (data, erasures) = encoding::decode_base58_with_erasures(text.into_iter().collect::<String>().as_str()).unwrap();
+            decode_share_with_erasures(data.as_slice(), erasures.as_slice()).unwrap()
+        }).collect();
+
+        assert_eq!(combine_shares(damaged.as_slice(), &secretbox).unwrap(), "supersecret".as_bytes());
+    }
+
+    #[test]
+    fn drill_is_the_same_with_any_shares_of_the_set() {
+        let (shares, secretbox) = split("supersecret".as_bytes(), 1.0, 3, 2).unwrap();
+
+        let first = drill(vec![shares[0].clone(), shares[2].clone()], &secretbox).unwrap();
+        let second = drill(vec![shares[1].clone(), shares[2].clone()], &secretbox).unwrap();
+        let (_, other_secretbox) = split("supersecret".as_bytes(), 1.0, 3, 2).unwrap();
+
+        assert_eq!(first.indices, vec![1, 3]);
+        assert_eq!(first.fingerprint, second.fingerprint);
+        assert_eq!(first.authenticate(b"record"), second.authenticate(b"record"));
+        assert_ne!(first.authenticate(b"record"), first.authenticate(b"other record"));
+        assert!(drill(shares, &other_secretbox).is_err());
+    }
+
+    #[test]
+    fn split_fingerprint_is_the_drill_one() {
+        let (shares, secretbox, fingerprint) = split_with_fingerprint("supersecret".as_bytes(), 3, 2).unwrap();
+        let (_, _, other_fingerprint) = split_with_fingerprint("supersecret".as_bytes(), 3, 2).unwrap();
+
+        assert_eq!(drill(shares[1..].to_vec(), &secretbox).unwrap().fingerprint, fingerprint);
+        assert_ne!(fingerprint, other_fingerprint);
+    }
+
+    #[test]
+    fn any_bytes_are_armored_and_unarmored() {
+        let data: Vec<u8> = (0..=255).collect();
+        let plan = EccPlan::new(data.len(), 0, 3);
+        for encoding in [TextEncoding::Base58, TextEncoding::Paper].iter() {
+            let text = armor(data.as_slice(), &plan, *encoding).unwrap();
+
+            let (unarmored, report) = unarmor(text.as_str(), *encoding).unwrap();
+
+            assert_eq!(unarmored, data);
+            assert_eq!(report.intact_plain_copies, 3);
+        }
+    }
+
+    #[test]
+    fn armor_with_missing_paper_lines_is_restored() {
+        let data = "critical config".as_bytes();
+        let plan = EccPlan::new(data.len(), 20, 1);
+        let text = armor(data, &plan, TextEncoding::Paper).unwrap();
+        // the line with the plain copy start is lost
+        let stored_data = add_ecc_and_crc_with_plan(data.to_vec(), &plan).unwrap();
+        let lost_line = stored_data.windows(data.len()).position(|w| w == data).unwrap() / paper::LINE_BYTES;
+        let without_line: Vec<&str> = text.lines().enumerate().filter(|(i, _)| *i != lost_line).map(|(_, l)| l).collect();
+
+        let (unarmored, report) = unarmor(without_line.join("\n").as_str(), TextEncoding::Paper).unwrap();
+
+        assert_eq!(unarmored, data);
+        assert!(report.repair.is_some());
+    }
+}
@@ -1,6 +1,12 @@
 extern crate serde;
 
 use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha512};
+use zeroize::Zeroize;
+
+use crate::checksum::MAX_CHECKSUM_LENGTH;
+
+pub const SET_ID_LENGTH: usize = 8;
 
 #[derive(Serialize, Deserialize, PartialEq, Debug)]
 pub struct StoredData {
@@ -17,6 +23,21 @@ pub struct ECCData {
 
 #[derive(Serialize, Deserialize, Eq, PartialEq, Debug, Hash, Copy, Clone)]
 pub struct Header {
+    #[serde(with = "varint")]
+    pub version: u64,
+    #[serde(with = "varint")]
+    pub encryption_algorithm: u64,
+    #[serde(with = "varint")]
+    pub data_len: u64,
+    #[serde(with = "varint")]
+    pub crc_algorithm: u64,
+    /// The data checksum padded with zeros
+    pub checksum: [u8; MAX_CHECKSUM_LENGTH],
+}
+
+/// The header of the format version 0 with the 16-bit checksum only
+#[derive(Serialize, Deserialize, Eq, PartialEq, Debug, Hash, Copy, Clone)]
+pub struct LegacyHeader {
     #[serde(with = "varint")]
     pub version: u64,
     #[serde(with = "varint")]
@@ -29,9 +50,60 @@ pub struct Header {
     pub crc1: u8,
 }
 
+impl From<LegacyHeader> for Header {
+    fn from(h: LegacyHeader) -> Header {
+        let mut checksum = [0; MAX_CHECKSUM_LENGTH];
+        checksum[0] = h.crc0;
+        checksum[1] = h.crc1;
+        Header { version: h.version, encryption_algorithm: h.encryption_algorithm, data_len: h.data_len, crc_algorithm: h.crc_algorithm, checksum }
+    }
+}
+
 /// Stores an encrypted message with a message authentication tag
-#[derive(Serialize, Deserialize, PartialEq, Debug)]
+#[derive(Serialize, Deserialize, PartialEq, Debug, Clone)]
 pub struct CryptoSecretbox {
     pub ciphertext: Vec<u8>,
     pub tag: Vec<u8>,
-}
\ No newline at end of file
+}
+impl CryptoSecretbox {
+    /// Identifies the shares of the secret in this box
+    pub fn set_id(&self) -> [u8; SET_ID_LENGTH] {
+        let mut hasher = Sha512::new();
+        hasher.update(self.ciphertext.as_slice());
+        hasher.update(self.tag.as_slice());
+        let mut set_id = [0; SET_ID_LENGTH];
+        set_id.copy_from_slice(&hasher.finalize()[..SET_ID_LENGTH]);
+        set_id
+    }
+}
+
+/// Key share with the information to combine it
+#[derive(Serialize, Deserialize, PartialEq, Debug, Clone)]
+pub struct Share {
+    /// The set id of the encrypted box, all zeros for the legacy shares
+    pub set_id: [u8; SET_ID_LENGTH],
+    /// Minimal number of shares to combine, 0 for the legacy shares
+    pub threshold: u8,
+    /// Number of shares in the set, 0 for the legacy shares
+    pub count: u8,
+    pub keyshare: Vec<u8>,
+}
+
+impl Share {
+    /// The share x coordinate
+    pub fn index(&self) -> u8 {
+        self.keyshare.first().cloned().unwrap_or(0)
+    }
+
+    /// The version 0 share is a raw key share without any information
+    pub fn is_legacy(&self) -> bool {
+        self.threshold == 0
+    }
+}
+
+/// Only the key share is secret
+impl Zeroize for Share {
+    fn zeroize(&mut self) {
+        self.keyshare.zeroize();
+    }
+}
@@ -0,0 +1,191 @@
+use std::collections::BTreeMap;
+
+use crc::{Crc, CRC_16_IBM_SDLC};
+
+use crate::encoding::UNKNOWN_CHARACTER;
+use crate::error::*;
+
+/// Bytes in a line, they're written as 24 base32 characters
+pub const LINE_BYTES: usize = 15;
+/// The base32 characters are written in groups to be read easily
+const GROUP_CHARACTERS: usize = 4;
+/// Crockford's base32 alphabet has no confusable characters
+const BASE32_CHARACTERS: &[u8; 32] = b"0123456789ABCDEFGHJKMNPQRSTVWXYZ";
+const CHECKSUM_CHARACTERS: usize = 4;
+
+const X25: Crc<u16> = Crc::<u16>::new(&CRC_16_IBM_SDLC);
+
+/// The data read from the lines
+#[derive(PartialEq, Debug, Clone)]
+pub struct Transcription {
+    pub data: Vec<u8>,
+    /// Positions of the bytes from the missing and damaged lines and the unknown characters
+    pub erasures: Vec<usize>,
+    /// Numbers of the missing lines and the lines with the wrong checksum
+    pub damaged_lines: Vec<usize>,
+}
+
+fn encode_base32(bytes: &[u8]) -> String {
+    let bits = bytes.len() * 8;
+    (0..(bits + 4) / 5).map(|i| {
+        let value = (0..5).fold(0usize, |value, bit| {
+            let position = i * 5 + bit;
+            let set = position < bits && bytes[position / 8] & (0x80 >> (position % 8)) != 0;
+            value << 1 | set as usize
+        });
+        BASE32_CHARACTERS[value] as char
+    }).collect()
+}
+
+/// Decodes Crockford's base32, the unknown characters are decoded as zero.
+/// Returns the bytes and the positions of the bytes with the unknown bits.
+fn decode_base32(text: &str) -> Option<(Vec<u8>, Vec<usize>)> {
+    let values: Vec<Option<u8>> = text.chars().map(|c| {
+        let c = match c.to_ascii_uppercase() {
+            'O' => '0',
+            'I' | 'L' => '1',
+            c => c,
+        };
+        if c == UNKNOWN_CHARACTER {
+            Some(None)
+        } else {
+            BASE32_CHARACTERS.iter().position(|b| *b as char == c).map(|v| Some(v as u8))
+        }
+    }).collect::<Option<Vec<Option<u8>>>>()?;
+    let mut bytes = vec![0u8; values.len() * 5 / 8];
+    let mut erasures = Vec::new();
+    for (i, value) in values.iter().enumerate() {
+        for bit in 0..5 {
+            let position = i * 5 + bit;
+            if position / 8 >= bytes.len() {
+                break;
+            }
+            match value {
+                Some(v) => if v & (0x10 >> bit) != 0 {
+                    bytes[position / 8] |= 0x80 >> (position % 8);
+                },
+                None => erasures.push(position / 8),
+            }
+        }
+    }
+    erasures.dedup();
+    Some((bytes, erasures))
+}
+
+/// The line checksum covers its number, so a line with the mistyped number is found too
+fn line_checksum(number: usize, total: usize, bytes: &[u8]) -> String {
+    let mut digest = X25.digest();
+    digest.update(&(number as u32).to_be_bytes());
+    digest.update(&(total as u32).to_be_bytes());
+    digest.update(bytes);
+    encode_base32(&digest.finalize().to_be_bytes())[..CHECKSUM_CHARACTERS].to_string()
+}
+
+/// Writes the data as the numbered lines to be copied by hand:
+/// `number/total`, the base32 characters in groups and the line checksum.
+pub fn encode_lines(data: &[u8]) -> String {
+    let chunks: Vec<&[u8]> = data.chunks(LINE_BYTES).collect();
+    let total = chunks.len();
+    let width = total.to_string().len();
+    chunks.iter().enumerate().map(|(i, chunk)| {
+        let characters = encode_base32(chunk);
+        let groups: Vec<String> = characters.as_bytes().chunks(GROUP_CHARACTERS)
+            .map(|group| String::from_utf8_lossy(group).to_string())
+            .collect();
+        format!("{:0width$}/{:0width$} {} {}", i + 1, total, groups.join(" "), line_checksum(i + 1, total, chunk), width = width)
+    }).collect::<Vec<String>>().join("\n")
+}
+
+/// The line number, total lines, bytes, positions of the unknown bytes and the checksum validity
+fn parse_line(line: &str) -> Option<(usize, usize, Vec<u8>, Vec<usize>, bool)> {
+    let tokens: Vec<&str> = line.split_whitespace().collect();
+    if tokens.len() < 3 {
+        return None;
+    }
+    let mut numbers = tokens[0].split('/').map(|n| n.parse::<usize>().ok());
+    let (number, total) = match (numbers.next().flatten(), numbers.next().flatten()) {
+        (Some(number), Some(total)) if number >= 1 && number <= total => (number, total),
+        _ => return None,
+    };
+    let checksum = tokens[tokens.len() - 1].to_ascii_uppercase();
+    let (bytes, erasures) = decode_base32(tokens[1..tokens.len() - 1].concat().as_str())?;
+    // the line with the unknown characters can't be checked, its ECC is left for the stored data
+    let valid = !erasures.is_empty() || line_checksum(number, total, bytes.as_slice()) == checksum;
+    Some((number, total, bytes, erasures, valid))
+}
+
+/// Checks a single line, so the mistyped line is found while it's typed
+pub fn is_valid_line(line: &str) -> bool {
+    parse_line(line).map(|(_, _, _, _, valid)| valid).unwrap_or(false)
+}
+
+/// Reads the lines in any order.
+/// The bytes of the missing and damaged lines are returned as erasures for the ECC of the stored data.
+pub fn decode_lines(text: &str) -> Result<Transcription> {
+    let parsed: Vec<(usize, usize, Vec<u8>, Vec<usize>, bool)> = text.lines().filter_map(parse_line).collect();
+    let valid: Vec<&(usize, usize, Vec<u8>, Vec<usize>, bool)> = parsed.iter().filter(|(_, _, _, _, valid)| *valid).collect();
+    let total = valid.iter().map(|(_, total, _, _, _)| *total).max().ok_or(Box::new(ErrorKind::EmptyData))?;
+    let lines: BTreeMap<usize, (&Vec<u8>, &Vec<usize>)> = valid.iter()
+        .filter(|(_, line_total, _, _, _)| *line_total == total)
+        .map(|(number, _, bytes, erasures, _)| (*number, (bytes, erasures)))
+        .collect();
+
+    let mut transcription = Transcription { data: Vec::new(), erasures: Vec::new(), damaged_lines: Vec::new() };
+    for number in 1..=total {
+        let offset = transcription.data.len();
+        match lines.get(&number) {
+            Some((bytes, erasures)) => {
+                transcription.data.extend_from_slice(bytes.as_slice());
+                transcription.erasures.extend(erasures.iter().map(|e| offset + e));
+            }
+            None => {
+                // the last line can be shorter, but its length is unknown
+                transcription.data.extend_from_slice(&[0; LINE_BYTES]);
+                transcription.erasures.extend(offset..offset + LINE_BYTES);
+                transcription.damaged_lines.push(number);
+            }
+        }
+    }
+    Ok(transcription)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn test_data() -> Vec<u8> {
+        (0..100).map(|i| (i * 37 + 11) as u8).collect()
+    }
+
+    #[test]
+    fn lines_are_read_in_any_order() {
+        let data = test_data();
+        let mut lines: Vec<String> = encode_lines(data.as_slice()).lines().map(|l| l.to_string()).collect();
+        lines.reverse();
+
+        let transcription = decode_lines(lines.join("\n").as_str()).unwrap();
+
+        assert_eq!(transcription.data, data);
+        assert!(transcription.erasures.is_empty());
+        assert!(lines.iter().all(|l| is_valid_line(l)));
+    }
+
+    #[test]
+    fn mistyped_and_missing_lines_are_erasures() {
+        let data = test_data();
+        let mut lines: Vec<String> = encode_lines(data.as_slice()).lines().map(|l| l.to_string()).collect();
+        // mistype a character of the second line and remove the third one
+        let mistyped = lines[1].replacen(&lines[1][8..9], if &lines[1][8..9] == "X" { "Y" } else { "X" }, 1);
+        assert!(!is_valid_line(mistyped.as_str()));
+        lines[1] = mistyped;
+        lines.remove(2);
+
+        let transcription = decode_lines(lines.join("\n").as_str()).unwrap();
+
+        assert_eq!(transcription.damaged_lines, vec![2, 3]);
+        assert_eq!(transcription.erasures, (LINE_BYTES..3 * LINE_BYTES).collect::<Vec<usize>>());
+        for (i, (a, b)) in transcription.data.iter().zip(data.iter()).enumerate() {
+            assert!(a == b || transcription.erasures.contains(&i));
+        }
+    }
+}
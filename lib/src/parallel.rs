@@ -0,0 +1,52 @@
+//! The checksum scans of the plain copy windows and the reading of several copies are spread over the rayon
+//! thread pool with the `parallel` feature. The results are taken in the order of the items, so they're equal
+//! to the serial ones. A single Reed-Solomon codeword is at most 255 bytes, so its work isn't spread.
+
+#[cfg(feature = "parallel")]
+use rayon::prelude::*;
+
+/// The same work on the current thread
+#[cfg_attr(feature = "parallel", allow(dead_code))]
+pub mod serial {
+    pub fn filter<T: Copy, F: Fn(&T) -> bool>(items: &[T], f: F) -> Vec<T> {
+        items.iter().cloned().filter(|item| f(item)).collect()
+    }
+
+    pub fn find_map_first<T, R, F: Fn(&T) -> Option<R>>(items: &[T], f: F) -> Option<R> {
+        items.iter().find_map(f)
+    }
+}
+
+/// Keeps the items order
+#[cfg(feature = "parallel")]
+pub fn filter<T: Copy + Sync + Send, F: Fn(&T) -> bool + Sync + Send>(items: &[T], f: F) -> Vec<T> {
+    items.par_iter().cloned().filter(|item| f(item)).collect()
+}
+
+#[cfg(not(feature = "parallel"))]
+pub use serial::filter;
+
+/// The result of the first item which has it, even if a later item is done earlier
+#[cfg(feature = "parallel")]
+pub fn find_map_first<T: Sync, R: Send, F: Fn(&T) -> Option<R> + Sync + Send>(items: &[T], f: F) -> Option<R> {
+    items.par_iter().find_map_first(f)
+}
+
+#[cfg(not(feature = "parallel"))]
+pub use serial::find_map_first;
+
+#[cfg(all(test, feature = "parallel"))]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parallel_results_are_equal_to_serial_ones() {
+        let items: Vec<usize> = (0..10000).collect();
+        let is_candidate = |i: &usize| i % 7 == 3;
+        let found = |i: &usize| if i % 1000 == 999 { Some(*i) } else { None };
+
+        assert_eq!(filter(items.as_slice(), is_candidate), serial::filter(items.as_slice(), is_candidate));
+        assert_eq!(find_map_first(items.as_slice(), found), Some(999));
+        assert_eq!(find_map_first(items.as_slice(), found), serial::find_map_first(items.as_slice(), found));
+    }
+}
@@ -0,0 +1,306 @@
+use std::cmp::{max, min};
+use std::str::FromStr;
+
+use crate::container::{HEADER_SECTION, SECTION_HEADER_LENGTH};
+use crate::error::*;
+use crate::serialization::{add_ecc_and_crc_with_plan, planned_sections};
+
+/// Reed-Solomon codeword can't be longer than 255 bytes (data and ECC)
+pub const REED_SOLOMON_MAX_CODEWORD_LEN: usize = 255;
+const MAX_PLAIN_COPIES: usize = 8;
+/// The plain copies of the damage level take no more bytes, but there're always two copies of the long data
+const MAX_DAMAGE_LEVEL_COPIES_LEN: usize = 1 << 16;
+/// Reed-Solomon ECC of the header restores it if all the header copies are damaged
+pub const HEADER_REED_SOLOMON_ECC_LEN: usize = 8;
+/// Every header copy is a section, so their number is limited
+const MAX_HEADER_COPIES: usize = 8;
+/// Damaged bytes which can lose any section: two bytes of its sync marker or of its tag copies
+const SECTION_LOSS_DAMAGE: usize = 2;
+
+/// The ECC algorithms and parameters to store the data
+#[derive(Eq, PartialEq, Debug, Copy, Clone)]
+pub struct EccPlan {
+    pub data_len: usize,
+    /// Length of Reed-Solomon ECC, 0 if it isn't used
+    pub reed_solomon_ecc_len: usize,
+    /// Number of plain data copies, including the original
+    pub copies: usize,
+    /// Number of header copies inserted in the stored data
+    pub header_copies: usize,
+    /// Length of Reed-Solomon ECC of the header, 0 if it isn't used
+    pub header_reed_solomon_ecc_len: usize,
+}
+
+/// The damage which the stored data should survive
+#[derive(Eq, PartialEq, Debug, Copy, Clone, Default)]
+pub struct DamageTarget {
+    /// Number of the damaged bytes in any positions of the stored data
+    pub random_bytes: usize,
+    /// Length of the sequential damaged bytes
+    pub burst_bytes: usize,
+}
+
+/// The typical storage media with their damage targets
+#[derive(Eq, PartialEq, Debug, Copy, Clone)]
+pub enum MediaProfile {
+    /// Printed or handwritten paper: stains, tears and mistyped characters
+    Paper,
+    /// QR code has its own error correction, only the rest of the damage is left
+    QRCode,
+    /// Flash or hard drive: the rare damaged sectors
+    Disk,
+}
+
+impl MediaProfile {
+    pub fn damage_target(&self) -> DamageTarget {
+        match self {
+            MediaProfile::Paper => DamageTarget { random_bytes: 4, burst_bytes: 16 },
+            MediaProfile::QRCode => DamageTarget { random_bytes: 2, burst_bytes: 0 },
+            MediaProfile::Disk => DamageTarget { random_bytes: 1, burst_bytes: 32 },
+        }
+    }
+}
+
+impl FromStr for MediaProfile {
+    type Err = String;
+
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "paper" => Ok(MediaProfile::Paper),
+            "qr" | "qrcode" => Ok(MediaProfile::QRCode),
+            "disk" => Ok(MediaProfile::Disk),
+            _ => Err(format!("Unknown media profile '{}', use paper, qr or disk", s)),
+        }
+    }
+}
+
+impl EccPlan {
+    /// The plan used by `create_ecc` for `allowed_data_damage_level`.
+    ///
+    /// The integer part of `data_len * allowed_data_damage_level` is the number of additional
+    /// plain copies. The fractional part `f` adds `data_len * floor(2 * f)` bytes of Reed-Solomon ECC,
+    /// so it's used only if `f >= 0.5`. If there's no fractional part and more than one additional
+    /// copy, the last copy is replaced with `2 * data_len` bytes of Reed-Solomon ECC.
+    /// Reed-Solomon ECC is cut to fit in one codeword with the data, then an additional copy is added.
+    /// The copies of the long data are limited by `MAX_DAMAGE_LEVEL_COPIES_LEN`.
+    /// The header copies are added as in `new`.
+    pub fn from_damage_level(data_len: usize, allowed_data_damage_level: f32) -> EccPlan {
+        let mut reed_solomon_damage_level = (data_len as f32 * allowed_data_damage_level) % 1.0;
+        let copy_damage_level = (data_len as f32 * allowed_data_damage_level) / 1.0;
+        let mut copy_ecc_times = copy_damage_level as usize;
+        if copy_ecc_times > 1 && reed_solomon_damage_level == 0.0 {
+            reed_solomon_damage_level = 1.0;
+            copy_ecc_times -= 1;
+        };
+        let mut reed_solomon_ecc_len = data_len * (2.0 * reed_solomon_damage_level) as usize;
+        let max_reed_solomon_ecc_len = REED_SOLOMON_MAX_CODEWORD_LEN.saturating_sub(data_len) / 2 * 2;
+        if reed_solomon_ecc_len > max_reed_solomon_ecc_len {
+            // the data is too long for the whole ECC in one codeword, the copy is stored instead
+            reed_solomon_ecc_len = max_reed_solomon_ecc_len;
+            copy_ecc_times += 1;
+        }
+        let max_copies = max(MAX_DAMAGE_LEVEL_COPIES_LEN / max(data_len, 1), 2);
+        EccPlan::new(data_len, reed_solomon_ecc_len, min(copy_ecc_times + 1, max_copies))
+    }
+
+    /// The plan with the given algorithms parameters.
+    /// Every byte of the damage the ECC survives adds a header copy up to `MAX_HEADER_COPIES`,
+    /// so the header isn't the weak part of the stored data unless the ECC is very strong.
+    pub fn new(data_len: usize, reed_solomon_ecc_len: usize, copies: usize) -> EccPlan {
+        let mut plan = EccPlan { data_len, reed_solomon_ecc_len, copies: max(copies, 1), header_copies: 0, header_reed_solomon_ecc_len: HEADER_REED_SOLOMON_ECC_LEN };
+        plan.header_copies = min(plan.guaranteed_ecc_damage() + 2, MAX_HEADER_COPIES);
+        plan
+    }
+
+    /// Length of all plain copies and ECC
+    pub fn ecc_len(&self) -> usize {
+        self.copies * self.data_len + self.reed_solomon_ecc_len
+    }
+
+    /// Length of the stored data with the headers
+    pub fn encoded_len(&self) -> Result<usize> {
+        add_ecc_and_crc_with_plan(vec![0xff; self.data_len], self).map(|r| r.len())
+    }
+
+    /// Number of the damaged bytes in any positions of the ECC sections which the data is guaranteed to survive.
+    ///
+    /// The data is restored from any intact plain copy, or from any plain copy with Reed-Solomon ECC
+    /// while they have no more than `reed_solomon_ecc_len / 2` errors in total. So every copy should be
+    /// damaged, and Reed-Solomon ECC is broken by damaging its half or by losing its section.
+    fn guaranteed_ecc_damage(&self) -> usize {
+        self.copies + min(self.reed_solomon_ecc_len / 2, SECTION_LOSS_DAMAGE) - 1
+    }
+
+    /// Number of the damaged bytes in any positions of the stored data which the data is guaranteed to survive.
+    ///
+    /// Besides the ECC, the damage can reach the header: it's read while any of its copies is intact.
+    pub fn guaranteed_random_damage(&self) -> usize {
+        min(self.guaranteed_ecc_damage(), self.header_copies.saturating_sub(1))
+    }
+
+    /// Length of the sequential damaged bytes in any position of the stored data which the data is guaranteed to survive
+    pub fn guaranteed_burst_damage(&self) -> usize {
+        let stored_len: usize = planned_sections(self)
+            .map(|sections| sections.iter().map(|(_, len)| SECTION_HEADER_LENGTH + len).sum())
+            .unwrap_or(0);
+        // if a burst is survived, all the shorter ones are survived too
+        let (mut survived, mut broken) = (0, stored_len + 1);
+        while broken - survived > 1 {
+            let middle = (survived + broken) / 2;
+            if self.survives_burst(middle) {
+                survived = middle;
+            } else {
+                broken = middle;
+            }
+        }
+        survived
+    }
+
+    /// Checks that the data survives a burst of `len` bytes in any position of the stored data.
+    ///
+    /// A section is lost if the burst damages `SECTION_LOSS_DAMAGE` bytes of its frame, so the header
+    /// needs a copy out of the burst, and the data needs a plain copy out of the burst or a plain copy
+    /// which is corrected by Reed-Solomon ECC.
+    pub fn survives_burst(&self, len: usize) -> bool {
+        let sections = match planned_sections(self) {
+            Ok(sections) => sections,
+            Err(_) => return false,
+        };
+        // the frame and the body of every section in the stored data
+        let mut regions: Vec<(u8, usize, usize, usize)> = Vec::new();
+        let mut from = 0;
+        for (tag, body_len) in sections {
+            regions.push((tag, from, from + SECTION_HEADER_LENGTH, from + SECTION_HEADER_LENGTH + body_len));
+            from += SECTION_HEADER_LENGTH + body_len;
+        }
+        let stored_len = from;
+        if len == 0 {
+            return true;
+        }
+        if len > stored_len {
+            return false;
+        }
+        let overlap = |burst_from: usize, block_from: usize, block_until: usize| {
+            min(burst_from + len, block_until).saturating_sub(max(burst_from, block_from))
+        };
+        // the damage changes only when the burst ends cross the regions bounds
+        let bounds: Vec<usize> = regions.iter().flat_map(|(_, from, body_from, until)| vec![*from, *body_from, *until]).collect();
+        let mut positions: Vec<usize> = bounds.iter()
+            .flat_map(|bound| vec![*bound as i64, *bound as i64 - len as i64])
+            .flat_map(|start| (-(SECTION_LOSS_DAMAGE as i64)..=SECTION_LOSS_DAMAGE as i64).map(move |k| start + k))
+            .filter(|start| *start >= 0 && *start as usize + len <= stored_len)
+            .map(|start| start as usize)
+            .collect();
+        positions.sort_unstable();
+        positions.dedup();
+        positions.into_iter().all(|burst_from| {
+            // the damaged body bytes of the sections which aren't lost
+            let damage: Vec<(u8, Option<usize>)> = regions.iter().map(|(tag, from, body_from, until)| {
+                let lost = overlap(burst_from, *from, *body_from) >= SECTION_LOSS_DAMAGE;
+                (*tag, if lost { None } else { Some(overlap(burst_from, *body_from, *until)) })
+            }).collect();
+            let header_survives = damage.iter().any(|(tag, damaged)| *tag == HEADER_SECTION && *damaged == Some(0));
+            let reed_solomon_errors = damage.iter().find(|(tag, _)| *tag == 1).and_then(|(_, damaged)| *damaged);
+            let data_survives = damage.iter().filter(|(tag, _)| *tag == 0).any(|(_, damaged)| match (damaged, reed_solomon_errors) {
+                (Some(0), _) => true,
+                (Some(copy_errors), Some(ecc_errors)) => 2 * (copy_errors + ecc_errors) <= self.reed_solomon_ecc_len,
+                _ => false,
+            });
+            header_survives && data_survives
+        })
+    }
+
+    pub fn satisfies(&self, target: &DamageTarget) -> bool {
+        self.guaranteed_random_damage() >= target.random_bytes && self.survives_burst(target.burst_bytes)
+    }
+}
+
+/// Finds the shortest plan which survives `target`
+pub fn plan(data_len: usize, target: &DamageTarget) -> Result<EccPlan> {
+    if data_len == 0 {
+        return Err(ErrorKind::EmptyData.into());
+    }
+    let max_reed_solomon_ecc_len = REED_SOLOMON_MAX_CODEWORD_LEN.saturating_sub(data_len);
+    let mut candidates: Vec<EccPlan> = (1..=MAX_PLAIN_COPIES)
+        .flat_map(|copies| {
+            // an odd byte of Reed-Solomon ECC doesn't add anything
+            (0..=max_reed_solomon_ecc_len).step_by(2)
+                .map(move |reed_solomon_ecc_len| EccPlan::new(data_len, reed_solomon_ecc_len, copies))
+        })
+        .collect();
+    candidates.sort_by_key(|p| (p.ecc_len(), p.copies));
+    candidates.into_iter()
+        .find(|p| p.satisfies(target))
+        .ok_or(ErrorKind::UnreachableDamageTarget.into())
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::container::read_sections;
+    use crate::serialization::try_to_read_stored_data;
+
+    use super::*;
+
+    #[test]
+    fn damage_level_plan_is_the_same_as_before() {
+        let plan = EccPlan::from_damage_level(10, 1.0);
+        assert_eq!(plan, EccPlan { data_len: 10, reed_solomon_ecc_len: 20, copies: 10, header_copies: 8, header_reed_solomon_ecc_len: 8 });
+        let plan = EccPlan::from_damage_level(10, 0.05);
+        assert_eq!(plan, EccPlan { data_len: 10, reed_solomon_ecc_len: 10, copies: 1, header_copies: 4, header_reed_solomon_ecc_len: 8 });
+    }
+
+    #[test]
+    fn damage_level_plan_fits_in_reed_solomon_codeword() {
+        let plan = EccPlan::from_damage_level(100, 1.0);
+        assert_eq!((plan.reed_solomon_ecc_len, plan.copies), (154, 101));
+        let plan = EccPlan::from_damage_level(1000, 0.5);
+        assert_eq!((plan.reed_solomon_ecc_len, plan.copies), (0, 65));
+        let plan = EccPlan::from_damage_level(1 << 20, 1.0);
+        assert_eq!((plan.reed_solomon_ecc_len, plan.copies), (0, 2));
+    }
+
+    #[test]
+    fn planned_sections_are_written() {
+        for plan in [EccPlan::new(10, 20, 3), EccPlan::from_damage_level(40, 1.0), EccPlan { header_copies: 1, header_reed_solomon_ecc_len: 0, ..EccPlan::new(300, 0, 2) }].iter() {
+            let encoded = add_ecc_and_crc_with_plan(vec![0xff; plan.data_len], plan).unwrap();
+            let written: Vec<(u8, usize)> = read_sections(encoded.as_slice()).iter().map(|s| (s.tag, s.body.len())).collect();
+
+            assert_eq!(planned_sections(plan).unwrap(), written);
+        }
+    }
+
+    #[test]
+    fn lost_section_is_counted_in_the_guaranteed_damage() {
+        let data = vec![7; 10];
+        let plan = EccPlan::new(data.len(), 20, 1);
+        let encoded = add_ecc_and_crc_with_plan(data.clone(), &plan).unwrap();
+        let sections = read_sections(encoded.as_slice());
+        let reed_solomon = sections.iter().find(|s| s.tag == 1).unwrap();
+        let copy = sections.iter().find(|s| s.tag == 0).unwrap();
+        let mut damaged = encoded.clone();
+        // the Reed-Solomon section marker and a byte of the only copy
+        let marker = reed_solomon.offset - SECTION_HEADER_LENGTH;
+        damaged[marker] = !damaged[marker];
+        damaged[marker + 1] = !damaged[marker + 1];
+        damaged[copy.offset] = !damaged[copy.offset];
+
+        assert_eq!(plan.guaranteed_random_damage(), 2);
+        assert!(try_to_read_stored_data(damaged.as_slice()).ok() != Some(data));
+    }
+
+    #[test]
+    fn planned_damage_is_guaranteed() {
+        let target = DamageTarget { random_bytes: 4, burst_bytes: 40 };
+        let plan = plan(100, &target).unwrap();
+
+        assert!(plan.guaranteed_random_damage() >= 4);
+        assert!(plan.guaranteed_burst_damage() >= 40);
+        assert!(!plan.survives_burst(plan.guaranteed_burst_damage() + 1));
+    }
+
+    #[test]
+    fn too_strong_target_is_unreachable() {
+        let target = DamageTarget { random_bytes: 20, burst_bytes: 0 };
+        assert!(plan(100, &target).is_err());
+    }
+}
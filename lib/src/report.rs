@@ -0,0 +1,112 @@
+use std::fmt;
+
+use crate::checksum::ChecksumAlgorithm;
+use crate::objects::Header;
+
+/// How the stored data was read and how close it was to be lost
+#[derive(PartialEq, Debug, Clone, Default)]
+pub struct RecoveryReport {
+    pub format_version: u64,
+    /// Number of the stored data copies read together
+    pub copies: usize,
+    /// Number of the found header copies
+    pub header_copies: usize,
+    /// Number of the header copies equal to the chosen one
+    pub agreeing_header_copies: usize,
+    pub plain_copies: usize,
+    /// Number of the plain copies with the valid checksum
+    pub intact_plain_copies: usize,
+    /// The ECC which restored the data if there's no intact plain copy
+    pub repair: Option<Repair>,
+}
+
+/// The data restored by ECC
+#[derive(Eq, PartialEq, Debug, Copy, Clone)]
+pub struct Repair {
+    pub ecc_algorithm: u64,
+    /// Number of the corrected bytes in the data and the ECC
+    pub corrected_bytes: usize,
+    /// Number of the known damaged positions passed to the ECC
+    pub erasures: usize,
+    /// Number of the errors in unknown positions which could be corrected additionally
+    pub margin: usize,
+}
+
+impl RecoveryReport {
+    /// The data is read only thanks to ECC or the header copies barely agree, so the stored data should be made again
+    pub fn needs_replacement(&self) -> bool {
+        self.intact_plain_copies == 0 || self.agreeing_header_copies * 2 <= self.header_copies
+    }
+}
+
+/// An ECC entry of the stored data as it's found
+#[derive(Eq, PartialEq, Debug, Copy, Clone)]
+pub struct EccEntry {
+    pub ecc_algorithm: u64,
+    pub len: usize,
+    /// The section checksum is valid, the version 0 has no section checksums
+    pub intact: Option<bool>,
+}
+
+/// What's inside the stored data, it's read as it is without restoring the data
+#[derive(PartialEq, Debug, Clone)]
+pub struct Layout {
+    pub format_version: u64,
+    /// The header of the most copies
+    pub header: Header,
+    pub header_copies: usize,
+    /// Number of the header copies equal to the chosen one
+    pub agreeing_header_copies: usize,
+    pub ecc_data: Vec<EccEntry>,
+}
+
+fn ecc_algorithm_name(ecc_algorithm: u64) -> &'static str {
+    match ecc_algorithm {
+        0 => "plain copy",
+        1 => "Reed-Solomon",
+        _ => "unknown ECC",
+    }
+}
+
+impl fmt::Display for RecoveryReport {
+    fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
+        writeln!(fmt, "Format version: {}", self.format_version)?;
+        if self.copies > 1 {
+            writeln!(fmt, "Read from {} copies together", self.copies)?;
+        }
+        writeln!(fmt, "Header copies: {} of {} agree", self.agreeing_header_copies, self.header_copies)?;
+        writeln!(fmt, "Plain copies: {} of {} intact", self.intact_plain_copies, self.plain_copies)?;
+        if let Some(repair) = self.repair {
+            writeln!(fmt, "Restored by {}: {} bytes corrected, {} known damaged positions, {} more errors can be corrected",
+                     ecc_algorithm_name(repair.ecc_algorithm), repair.corrected_bytes, repair.erasures, repair.margin)?;
+        }
+        if self.needs_replacement() {
+            write!(fmt, "The stored data is damaged, please make a new copy")
+        } else {
+            write!(fmt, "The stored data is fine")
+        }
+    }
+}
+
+impl fmt::Display for Layout {
+    fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
+        let header = &self.header;
+        writeln!(fmt, "Format version: {}", self.format_version)?;
+        writeln!(fmt, "Header: encryption algorithm {}, data length {} bytes", header.encryption_algorithm, header.data_len)?;
+        match ChecksumAlgorithm::from_id(header.crc_algorithm) {
+            Some(algorithm) => writeln!(fmt, "Checksum: {:?} {}", algorithm,
+                                        header.checksum[..algorithm.len()].iter().map(|b| format!("{:02x}", b)).collect::<String>())?,
+            None => writeln!(fmt, "Checksum: unknown algorithm {}", header.crc_algorithm)?,
+        }
+        write!(fmt, "Header copies: {} of {} agree", self.agreeing_header_copies, self.header_copies)?;
+        for entry in self.ecc_data.iter() {
+            write!(fmt, "\nECC: {}, {} bytes", ecc_algorithm_name(entry.ecc_algorithm), entry.len)?;
+            match entry.intact {
+                Some(true) => write!(fmt, ", intact")?,
+                Some(false) => write!(fmt, ", damaged")?,
+                None => {}
+            }
+        }
+        Ok(())
+    }
+}
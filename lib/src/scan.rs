@@ -0,0 +1,264 @@
+use std::collections::{HashMap, HashSet};
+
+use integer_encoding::VarInt;
+
+use crate::objects::Header;
+use crate::parallel;
+use crate::serialization::{checksum_matches, decode_chunk_len};
+
+/// Every window is checked by the checksum only while it takes no more than this number of bytes to hash
+pub const EXHAUSTIVE_SCAN_LIMIT: usize = 1 << 24;
+/// The base of the polynomial rolling hash, an odd number to keep it invertible modulo 2^64
+const ROLLING_HASH_BASE: u64 = 0x100000001b3;
+
+/// Offsets of the ECC bodies in the postcard serialized `StoredData`, walked as far as it can be parsed.
+/// A damaged length is replaced by the data length, so the walk goes on to the next plain copy.
+pub fn structural_offsets(payload: &[u8], data_len: usize) -> Vec<usize> {
+    let mut offsets = Vec::new();
+    let mut position = match decode_chunk_len(payload) {
+        Some((_, len)) => len,
+        None => return offsets,
+    };
+    while position < payload.len() {
+        let algorithm_len = match decode_chunk_len(&payload[position..]) {
+            Some((_, len)) => len,
+            None => break,
+        };
+        let (body_len, len_len) = match decode_chunk_len(&payload[position + algorithm_len..]) {
+            Some(body_len) => body_len,
+            None => break,
+        };
+        let body = position + algorithm_len + len_len;
+        offsets.push(body);
+        position = body + if body_len <= payload.len() - body { body_len } else { data_len };
+    }
+    offsets
+}
+
+/// Offsets of the bodies after the postcard prefix of a plain copy of `data_len` bytes, the first ones
+/// up to `limit`. They're found in any position, so a copy shifted by the inserted or lost bytes is found too.
+pub fn prefixed_offsets(payload: &[u8], data_len: usize, limit: usize) -> Vec<usize> {
+    let mut prefix = vec![0u8];
+    prefix.extend(data_len.encode_var_vec());
+    payload.windows(prefix.len()).enumerate()
+        .filter(|(_, window)| *window == prefix.as_slice())
+        .map(|(start, _)| start + prefix.len())
+        .take(limit)
+        .collect()
+}
+
+/// The polynomial rolling hash of every window of `bytes` in order, the hashes aren't kept
+fn rolling_hashes(bytes: &[u8], window_len: usize) -> impl Iterator<Item=(usize, u64)> + '_ {
+    let windows = if window_len == 0 || bytes.len() < window_len { 0 } else { bytes.len() - window_len + 1 };
+    let highest_power = (1..window_len).fold(1u64, |power, _| power.wrapping_mul(ROLLING_HASH_BASE));
+    let mut hash = 0u64;
+    (0..windows).map(move |start| {
+        hash = if start == 0 {
+            bytes[..window_len].iter().fold(0u64, |hash, b| hash.wrapping_mul(ROLLING_HASH_BASE).wrapping_add(*b as u64))
+        } else {
+            hash.wrapping_sub((bytes[start - 1] as u64).wrapping_mul(highest_power))
+                .wrapping_mul(ROLLING_HASH_BASE)
+                .wrapping_add(bytes[start + window_len - 1] as u64)
+        };
+        (start, hash)
+    })
+}
+
+/// The first windows of the pairs of equal windows, up to `limit` of them.
+/// The intact plain copies are equal, so they're found in one pass without hashing every window.
+///
+/// Only the windows at every `step` bytes are kept in the map, every window of `window_len` has one of them
+/// in its first `step` bytes, so the memory is proportional to `bytes.len() / window_len`.
+/// The equal windows at every `step` are kept once, so the data repeated inside itself can hide a pair,
+/// then the copy is found by the checksum scan.
+pub fn repeated_windows(bytes: &[u8], window_len: usize, limit: usize) -> Vec<usize> {
+    if window_len == 0 || bytes.len() < window_len {
+        return Vec::new();
+    }
+    let step = (window_len + 1) / 2;
+    let anchor_len = window_len - step + 1;
+    let mut anchors: HashMap<u64, usize> = HashMap::new();
+    for (start, hash) in rolling_hashes(bytes, anchor_len).step_by(step) {
+        anchors.entry(hash).or_insert(start);
+    }
+    let mut found: HashSet<usize> = HashSet::new();
+    let mut result = Vec::new();
+    for (start, hash) in rolling_hashes(bytes, anchor_len) {
+        let anchor = match anchors.get(&hash) {
+            Some(anchor) if *anchor < start && bytes[*anchor..*anchor + anchor_len] == bytes[start..start + anchor_len] => *anchor,
+            _ => continue,
+        };
+        // the equal bytes around the anchor, only the windows which have it in their first `step` bytes are taken
+        let before = (1..step).take_while(|i| *i <= anchor && bytes[anchor - i] == bytes[start - i]).count();
+        let after = (0..step - 1)
+            .take_while(|i| start + anchor_len + i < bytes.len() && bytes[anchor + anchor_len + i] == bytes[start + anchor_len + i])
+            .count();
+        let last_window = match (anchor + anchor_len + after).checked_sub(window_len) {
+            Some(last_window) => last_window.min(anchor),
+            None => continue,
+        };
+        for window in anchor - before..=last_window {
+            if found.insert(window) {
+                result.push(window);
+            }
+        }
+        if result.len() >= limit {
+            result.truncate(limit);
+            break;
+        }
+    }
+    result
+}
+
+/// Every window start up to `last`, the nearest to the sorted `centers` first.
+/// A position between two centers is taken from the left one.
+fn nearest_first(centers: &[usize], last: usize) -> impl Iterator<Item=usize> + '_ {
+    let is_center = move |position: usize| centers.binary_search(&position).is_ok();
+    let nearest_distance = move |position: usize| {
+        let i = centers.partition_point(|c| *c < position);
+        let right = centers.get(i).map(|c| c - position);
+        let left = i.checked_sub(1).map(|i| position - centers[i]);
+        left.into_iter().chain(right).min().unwrap_or(usize::MAX)
+    };
+    (0..=last).flat_map(move |distance| {
+        centers.iter().flat_map(move |center| {
+            let before = center.checked_sub(distance)
+                .filter(|position| distance > 0 && !(*position >= distance && is_center(position - distance)));
+            let after = Some(center + distance);
+            before.into_iter().chain(after)
+                .filter(move |position| *position <= last && nearest_distance(*position) == distance)
+        })
+    })
+}
+
+/// Number of the windows checked by the checksum at once
+const SCAN_BATCH_LEN: usize = 1024;
+
+/// Finds the offsets of the intact plain copies of the data in `bytes`.
+///
+/// The structural candidates are checked first, then the repeated windows, and then every window by the distance
+/// to the structural candidates while no more than `EXHAUSTIVE_SCAN_LIMIT` bytes are hashed. So a single copy is
+/// found in any position of the small `bytes` and next to the structural candidates in the large ones.
+pub fn find_plain_copies(header: &Header, bytes: &[u8], structural: &[usize]) -> Vec<usize> {
+    let data_len = header.data_len as usize;
+    if data_len == 0 || bytes.len() < data_len {
+        return Vec::new();
+    }
+    let last = bytes.len() - data_len;
+    let scan_limit = (EXHAUSTIVE_SCAN_LIMIT / data_len).max(1);
+    let is_copy = |start: &usize| *start <= last && checksum_matches(header, &bytes[*start..*start + data_len]);
+    let mut found: Vec<usize> = parallel::filter(structural, is_copy);
+    if found.is_empty() {
+        found = parallel::filter(repeated_windows(bytes, data_len, scan_limit).as_slice(), is_copy);
+    }
+    if found.is_empty() {
+        let mut centers: Vec<usize> = structural.iter().cloned().filter(|start| *start <= last).chain([0, last]).collect();
+        centers.sort();
+        centers.dedup();
+        let mut windows = nearest_first(centers.as_slice(), last).take(scan_limit).peekable();
+        let mut batch = Vec::with_capacity(SCAN_BATCH_LEN);
+        while found.is_empty() && windows.peek().is_some() {
+            batch.clear();
+            batch.extend(windows.by_ref().take(SCAN_BATCH_LEN));
+            found = parallel::filter(batch.as_slice(), is_copy);
+        }
+    }
+    // the other copies are equal to the found one, so they're found by the rolling hash and comparison
+    match found.first() {
+        Some(first) => {
+            let copy = &bytes[*first..*first + data_len];
+            let copy_hash = rolling_hashes(copy, data_len).next().map(|(_, hash)| hash);
+            rolling_hashes(bytes, data_len)
+                .filter(|(start, hash)| Some(*hash) == copy_hash && &bytes[*start..*start + data_len] == copy)
+                .map(|(start, _)| start)
+                .collect()
+        }
+        None => found,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::checksum::DEFAULT_CHECKSUM_ALGORITHM;
+    use crate::serialization::FORMAT_VERSION;
+
+    use super::*;
+
+    fn noise(len: usize, seed: u64) -> Vec<u8> {
+        (0..len as u64).map(|i| ((i + seed * 1000).wrapping_mul(0x9e3779b97f4a7c15) >> 29) as u8).collect()
+    }
+
+    fn header_of(data: &[u8]) -> Header {
+        Header {
+            version: FORMAT_VERSION,
+            encryption_algorithm: 0,
+            data_len: data.len() as u64,
+            crc_algorithm: DEFAULT_CHECKSUM_ALGORITHM.id(),
+            checksum: DEFAULT_CHECKSUM_ALGORITHM.checksum(data),
+        }
+    }
+
+    #[test]
+    fn repeated_windows_are_found() {
+        let data = noise(50, 1);
+        let bytes = [noise(13, 2), data.clone(), noise(7, 3), data.clone(), noise(20, 4)].concat();
+
+        assert_eq!(repeated_windows(bytes.as_slice(), data.len(), 100), vec![13]);
+    }
+
+    #[test]
+    fn windows_are_taken_by_the_distance_to_the_centers() {
+        let windows: Vec<usize> = nearest_first(&[2, 6], 9).collect();
+
+        assert_eq!(windows, vec![2, 6, 1, 3, 5, 7, 0, 4, 8, 9]);
+    }
+
+    #[test]
+    fn single_shifted_copy_is_found_in_large_data() {
+        let data = noise(4096, 1);
+        let header = header_of(data.as_slice());
+        let bytes = [noise(5007, 2), data.clone(), noise(3000, 3)].concat();
+        assert!((bytes.len() - data.len() + 1) * data.len() > EXHAUSTIVE_SCAN_LIMIT);
+
+        // the structure points 7 bytes before the copy
+        assert_eq!(find_plain_copies(&header, bytes.as_slice(), &[5000]), vec![5007]);
+    }
+
+    #[test]
+    fn prefixed_copies_are_found() {
+        let data = noise(300, 1);
+        let prefix = [0, 0xac, 0x02];
+        let bytes = [noise(1000, 2), prefix.to_vec(), data.clone(), noise(10, 3), prefix.to_vec(), data].concat();
+
+        assert_eq!(prefixed_offsets(bytes.as_slice(), 300, 64), vec![1003, 1316]);
+        assert_eq!(prefixed_offsets(bytes.as_slice(), 300, 1), vec![1003]);
+    }
+
+    #[test]
+    fn plain_copies_are_found_in_any_position() {
+        let data = noise(50, 1);
+        let header = header_of(data.as_slice());
+        let mut damaged = data.clone();
+        damaged[10] ^= 1;
+        let bytes = [noise(13, 2), data.clone(), damaged, data.clone(), noise(20, 4)].concat();
+
+        assert_eq!(find_plain_copies(&header, bytes.as_slice(), &[]), vec![13, 113]);
+        assert_eq!(find_plain_copies(&header, bytes.as_slice(), &[113]), vec![13, 113]);
+        // the single copy is found by the exhaustive scan
+        assert_eq!(find_plain_copies(&header, &bytes[..100], &[]), vec![13]);
+    }
+
+    #[test]
+    fn structural_offsets_follow_the_stored_data() {
+        let data = noise(20, 1);
+        let stored_data = crate::objects::StoredData {
+            data: vec![
+                crate::objects::ECCData { ecc_algorithm: 1, ecc: noise(8, 2) },
+                crate::objects::ECCData { ecc_algorithm: 0, ecc: data.clone() },
+            ]
+        };
+        let payload = postcard::to_allocvec(&stored_data).unwrap();
+
+        assert_eq!(structural_offsets(payload.as_slice(), data.len()), vec![3, 13]);
+    }
+}
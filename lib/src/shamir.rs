@@ -4,31 +4,48 @@ extern crate rand;
 extern crate shamirsecretsharing;
 
 use shamirsecretsharing::hazmat::{combine_keyshares, create_keyshares};
+use zeroize::{Zeroize, Zeroizing};
 
 use crate::error::*;
 use crate::objects::CryptoSecretbox;
 
-pub fn create_data_shares(data: &[u8], count: u8, threshold: u8) -> Result<(Vec<Vec<u8>>, CryptoSecretbox)> {
+/// Passes the ephemeral key to `f` before it's zeroized, the key shares are zeroized when they're dropped
+pub fn create_data_shares<R, F: FnOnce(&[u8]) -> R>(data: &[u8], count: u8, threshold: u8, f: F) -> Result<(Zeroizing<Vec<Vec<u8>>>, CryptoSecretbox, R)> {
     // Generate an ephemeral key
-    let ref key = rand::random::<[u8; 32]>();
+    let key = Zeroizing::new(rand::random::<[u8; 32]>());
 
     // Encrypt the text using the key
-    let result = aead_wrap(key, data)
+    let result = aead_wrap(key.as_slice(), data)
         .and_then(|boxed|
             // Share the key using `create_keyshares`
-            create_keyshares(key, count, threshold)
-                .map(|keyshares| (keyshares, boxed)).map_err(|ssse| ErrorKind::ShamirsSecretSharingEncryptionError(ssse).into()));
+            create_keyshares(key.as_slice(), count, threshold)
+                .map(|keyshares| (Zeroizing::new(keyshares), boxed, f(key.as_slice()))).map_err(|ssse| ErrorKind::ShamirsSecretSharingEncryptionError(ssse).into()));
 
     return result;
 }
 
-pub fn combine_data_shares(shares: Vec<Vec<u8>>, b: &CryptoSecretbox) -> Result<Vec<u8>> {
+pub fn combine_data_shares(shares: &[Vec<u8>], b: &CryptoSecretbox) -> Result<Vec<u8>> {
     // Recover the key using `combine_keyshares`
-    combine_keyshares(&shares)
+    combine_keyshares(shares)
         .map_err(|e| ErrorKind::ShamirsSecretSharingDecryptionError(e).into())
-        .and_then(|key|
+        .and_then(|mut key| {
             // Decrypt the secret message using the restored key
-            aead_unwrap(&key, b))
+            let result = aead_unwrap(&key, b);
+            key.zeroize();
+            result
+        })
+}
+
+/// Passes the recovered key and the secret to `f`, then zeroizes them, so only the result of `f` is left
+pub fn inspect_data_shares<R, F: FnOnce(&[u8], &[u8]) -> R>(shares: &[Vec<u8>], b: &CryptoSecretbox, f: F) -> Result<R> {
+    let mut key = combine_keyshares(shares).map_err(|e| Box::new(ErrorKind::ShamirsSecretSharingDecryptionError(e)))?;
+    let result = aead_unwrap(&key, b).map(|mut text| {
+        let result = f(key.as_slice(), text.as_slice());
+        text.zeroize();
+        result
+    });
+    key.zeroize();
+    result
 }
 
 /// AEAD encrypt the message with `key`
@@ -0,0 +1,190 @@
+use std::fmt;
+use std::str::FromStr;
+
+use rand::{Rng, SeedableRng};
+use rand::rngs::StdRng;
+use rand::seq::index::sample;
+
+use crate::encoding::BASE58_CHARACTERS;
+use crate::error::*;
+use crate::serialization::{add_ecc_and_crc, try_to_read_stored_data};
+
+/// The damage models applied to the stored data
+#[derive(PartialEq, Debug, Copy, Clone)]
+pub enum Damage {
+    /// Part of the bytes replaced by random values in random positions
+    RandomBytes(f32),
+    /// Sequential bytes replaced by random values in a random position
+    Burst(usize),
+    /// Part of the bits flipped in random positions
+    BitFlips(f32),
+    /// Number of the bytes cut from the start or the end
+    Truncation(usize),
+    /// Number of the mistyped characters of the base58 text: replaced, swapped, missed or extra ones
+    Typos(usize),
+}
+
+impl FromStr for Damage {
+    type Err = String;
+
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        let mut parts = s.splitn(2, ':');
+        let (model, value) = (parts.next().unwrap_or(""), parts.next().unwrap_or(""));
+        let invalid_value = || format!("Invalid damage value in '{}'", s);
+        match model.to_lowercase().as_str() {
+            "random" => value.parse().map(Damage::RandomBytes).map_err(|_| invalid_value()),
+            "burst" => value.parse().map(Damage::Burst).map_err(|_| invalid_value()),
+            "bits" => value.parse().map(Damage::BitFlips).map_err(|_| invalid_value()),
+            "cut" => value.parse().map(Damage::Truncation).map_err(|_| invalid_value()),
+            "typos" => value.parse().map(Damage::Typos).map_err(|_| invalid_value()),
+            _ => Err(format!("Unknown damage model '{}', use random:<fraction>, burst:<bytes>, bits:<fraction>, cut:<bytes> or typos:<count>", s)),
+        }
+    }
+}
+
+impl fmt::Display for Damage {
+    fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Damage::RandomBytes(fraction) => write!(fmt, "{:.1}% random bytes", fraction * 100.0),
+            Damage::Burst(len) => write!(fmt, "burst of {} bytes", len),
+            Damage::BitFlips(fraction) => write!(fmt, "{:.1}% flipped bits", fraction * 100.0),
+            Damage::Truncation(len) => write!(fmt, "{} bytes cut", len),
+            Damage::Typos(count) => write!(fmt, "{} typos in base58", count),
+        }
+    }
+}
+
+/// The outcome of the trials of a damage model
+#[derive(Eq, PartialEq, Debug, Copy, Clone)]
+pub struct SimulationResult {
+    pub trials: usize,
+    pub recovered: usize,
+    /// Number of the trials read without an error, but with the wrong data
+    pub miscorrected: usize,
+}
+
+impl SimulationResult {
+    pub fn recovery_rate(&self) -> f32 {
+        self.recovered as f32 / self.trials as f32
+    }
+}
+
+fn damage_bytes(data: &mut Vec<u8>, damage: &Damage, rng: &mut StdRng) {
+    let len = data.len();
+    match *damage {
+        Damage::RandomBytes(fraction) => {
+            let count = ((len as f32 * fraction).round() as usize).min(len);
+            for i in sample(rng, len, count) {
+                data[i] = data[i].wrapping_add(rng.gen_range(1..=255));
+            }
+        }
+        Damage::Burst(burst_len) => {
+            let burst_len = burst_len.min(len);
+            let start = rng.gen_range(0..=len - burst_len);
+            for b in data[start..start + burst_len].iter_mut() {
+                *b = b.wrapping_add(rng.gen_range(1..=255));
+            }
+        }
+        Damage::BitFlips(fraction) => {
+            let count = ((len as f32 * 8.0 * fraction).round() as usize).min(len * 8);
+            for bit in sample(rng, len * 8, count) {
+                data[bit / 8] ^= 0x80 >> (bit % 8);
+            }
+        }
+        Damage::Truncation(cut_len) => {
+            let cut_len = cut_len.min(len);
+            if rng.gen() {
+                data.drain(..cut_len);
+            } else {
+                data.truncate(len - cut_len);
+            }
+        }
+        Damage::Typos(_) => {}
+    }
+}
+
+fn random_base58_character(rng: &mut StdRng) -> char {
+    BASE58_CHARACTERS.as_bytes()[rng.gen_range(0..BASE58_CHARACTERS.len())] as char
+}
+
+fn damage_text(text: &str, typos: usize, rng: &mut StdRng) -> String {
+    let mut chars: Vec<char> = text.chars().collect();
+    for _ in 0..typos {
+        if chars.len() < 2 {
+            break;
+        }
+        let i = rng.gen_range(0..chars.len() - 1);
+        match rng.gen_range(0..4) {
+            0 => chars[i] = random_base58_character(rng),
+            1 => chars.swap(i, i + 1),
+            2 => { chars.remove(i); }
+            _ => chars.insert(i, random_base58_character(rng)),
+        }
+    }
+    chars.into_iter().collect()
+}
+
+/// Stores the data with `allowed_data_damage_level`, damages it in every trial and tries to read it back.
+/// The trials are reproducible by the seed.
+pub fn simulate(data: &[u8], allowed_data_damage_level: f32, damage: &Damage, trials: usize, seed: u64) -> Result<SimulationResult> {
+    let stored_data = add_ecc_and_crc(data.to_vec(), allowed_data_damage_level)?;
+    let text = bs58::encode(&stored_data).into_string();
+    let mut rng = StdRng::seed_from_u64(seed);
+    let mut result = SimulationResult { trials, recovered: 0, miscorrected: 0 };
+    for _ in 0..trials {
+        let damaged = match damage {
+            Damage::Typos(typos) => match bs58::decode(damage_text(text.as_str(), *typos, &mut rng)).into_vec() {
+                Ok(damaged) => damaged,
+                Err(_) => continue,
+            },
+            _ => {
+                let mut damaged = stored_data.clone();
+                damage_bytes(&mut damaged, damage, &mut rng);
+                damaged
+            }
+        };
+        match try_to_read_stored_data(damaged.as_slice()) {
+            Ok(read) if read == data => result.recovered += 1,
+            Ok(_) => result.miscorrected += 1,
+            Err(_) => {}
+        }
+    }
+    Ok(result)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn damage_models_are_parsed() {
+        assert_eq!("random:0.1".parse::<Damage>(), Ok(Damage::RandomBytes(0.1)));
+        assert_eq!("burst:16".parse::<Damage>(), Ok(Damage::Burst(16)));
+        assert_eq!("bits:0.01".parse::<Damage>(), Ok(Damage::BitFlips(0.01)));
+        assert_eq!("cut:4".parse::<Damage>(), Ok(Damage::Truncation(4)));
+        assert_eq!("typos:2".parse::<Damage>(), Ok(Damage::Typos(2)));
+        assert!("stains:3".parse::<Damage>().is_err());
+        assert!("burst:x".parse::<Damage>().is_err());
+    }
+
+    #[test]
+    fn simulation_is_reproducible_and_survives_the_planned_damage() {
+        let data = "1234567890".as_bytes();
+        let damage = Damage::Burst(8);
+
+        let result = simulate(data, 1.0, &damage, 50, 7).unwrap();
+
+        assert_eq!(result, simulate(data, 1.0, &damage, 50, 7).unwrap());
+        assert_eq!(result.recovered, 50);
+        assert_eq!(result.miscorrected, 0);
+    }
+
+    #[test]
+    fn heavy_damage_lowers_the_recovery_rate() {
+        let data = "1234567890".as_bytes();
+
+        let result = simulate(data, 1.0, &Damage::RandomBytes(0.9), 20, 7).unwrap();
+
+        assert!(result.recovery_rate() < 1.0);
+    }
+}
@@ -0,0 +1,7 @@
+//! The golden vectors of `lib/test-vectors` for the tests
+
+/// Reads the hex of the golden vector file
+pub fn from_hex(text: &str) -> Vec<u8> {
+    let text = text.trim();
+    (0..text.len()).step_by(2).map(|i| u8::from_str_radix(&text[i..i + 2], 16).unwrap()).collect()
+}
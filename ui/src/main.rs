@@ -4,7 +4,6 @@ use std::cell::RefCell;
 use std::collections::HashMap;
 use std::rc::Rc;
 
-use heapless::consts::U16384;
 use itertools::Itertools;
 use iui::controls::{Button, Entry, GridAlignment, GridExpand, HorizontalSeparator, Label,
                     LayoutGrid, MultilineEntry, ProgressBar, Slider, Spinbox};
@@ -30,8 +29,8 @@ fn split(secret: &String, count: u8, threshold: u8) -> Result<(String, Vec<Strin
     let allowed_data_damage_level = 1.0;
 
     dataragon::split(text, allowed_data_damage_level, count, threshold).and_then(|(shares, secret_box)| {
-        let encoded_secret_box: heapless::Vec<u8, U16384> = postcard::to_vec(&secret_box).unwrap();
-        return serialization::add_ecc_and_crc(encoded_secret_box.to_vec(), allowed_data_damage_level).map(|encoded_secret_box_with_ecc_and_crc| {
+        let encoded_secret_box = serialization::to_bytes(&secret_box)?;
+        return serialization::add_ecc_and_crc(encoded_secret_box, allowed_data_damage_level).map(|encoded_secret_box_with_ecc_and_crc| {
             (bs58::encode(encoded_secret_box_with_ecc_and_crc).into_string(), shares.map(|s| bs58::encode(s).into_string()))
         });
     })